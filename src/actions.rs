@@ -0,0 +1,369 @@
+use std::collections::{BTreeMap, HashSet};
+
+use bevy::ecs as bevy_ecs;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+
+/// Everything the canvas can be asked to do, independent of the key or
+/// button bound to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Select,
+    SpawnLight,
+    DragPan,
+    Delete,
+    CycleEmitter,
+    CycleShading,
+    ZoomIn,
+    ZoomOut,
+    ResetZoom,
+    PanLeft,
+    PanRight,
+    PanUp,
+    PanDown,
+    PlayPause,
+    StepBack,
+    StepForward,
+    Rewind,
+    CyclePath,
+    ToggleMirror,
+    CycleBounceDepth,
+    CycleTransmittance,
+    AddVisitor,
+    AddCamera,
+    RotateCamera,
+    ToggleSurveillance,
+    ToggleProbe,
+    Solo,
+    Mute,
+    Ruler,
+    PinDimension,
+    ToggleHelp,
+}
+
+impl Action {
+    pub const ALL: [Action; 31] = [
+        Action::Select,
+        Action::SpawnLight,
+        Action::DragPan,
+        Action::Delete,
+        Action::CycleEmitter,
+        Action::CycleShading,
+        Action::ZoomIn,
+        Action::ZoomOut,
+        Action::ResetZoom,
+        Action::PanLeft,
+        Action::PanRight,
+        Action::PanUp,
+        Action::PanDown,
+        Action::PlayPause,
+        Action::StepBack,
+        Action::StepForward,
+        Action::Rewind,
+        Action::CyclePath,
+        Action::ToggleMirror,
+        Action::CycleBounceDepth,
+        Action::CycleTransmittance,
+        Action::AddVisitor,
+        Action::AddCamera,
+        Action::RotateCamera,
+        Action::ToggleSurveillance,
+        Action::ToggleProbe,
+        Action::Solo,
+        Action::Mute,
+        Action::Ruler,
+        Action::PinDimension,
+        Action::ToggleHelp,
+    ];
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Select => "Select and drag",
+            Action::SpawnLight => "Place a light (click)",
+            Action::DragPan => "Pan (drag)",
+            Action::Delete => "Delete the selection",
+            Action::CycleEmitter => "Cycle the emitter shape",
+            Action::CycleShading => "Cycle the shading mode",
+            Action::ZoomIn => "Zoom in",
+            Action::ZoomOut => "Zoom out",
+            Action::ResetZoom => "Fit the room",
+            Action::PanLeft => "Pan left",
+            Action::PanRight => "Pan right",
+            Action::PanUp => "Pan up",
+            Action::PanDown => "Pan down",
+            Action::PlayPause => "Play or pause",
+            Action::StepBack => "Half a second back",
+            Action::StepForward => "Half a second forward",
+            Action::Rewind => "Rewind",
+            Action::CyclePath => "Cycle the light's path",
+            Action::ToggleMirror => "Toggle the mirror edge at the cursor",
+            Action::CycleBounceDepth => "Cycle the mirror bounces",
+            Action::CycleTransmittance => "Cycle the transmittance at the cursor",
+            Action::AddVisitor => "Add a visitor",
+            Action::AddCamera => "Add a camera",
+            Action::RotateCamera => "Turn the camera",
+            Action::ToggleSurveillance => "Show camera coverage",
+            Action::ToggleProbe => "Probe the lights at the cursor",
+            Action::Solo => "Solo the light",
+            Action::Mute => "Mute the light",
+            Action::Ruler => "Measure (click two points)",
+            Action::PinDimension => "Keep the measurement",
+            Action::ToggleHelp => "Show the controls",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButtonType),
+}
+
+impl Binding {
+    pub fn label(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{key:?}"),
+            Binding::Mouse(button) => format!("{button:?} mouse"),
+            Binding::Gamepad(button) => format!("Pad {button:?}"),
+        }
+    }
+}
+
+/// The bindings of every action. Actions missing from the settings file keep
+/// their default bindings.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    from = "BTreeMap<Action, Vec<Binding>>",
+    into = "BTreeMap<Action, Vec<Binding>>"
+)]
+pub struct Bindings(BTreeMap<Action, Vec<Binding>>);
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use Binding::{Gamepad, Key, Mouse};
+        use GamepadButtonType as Pad;
+        let bindings = |action: Action| match action {
+            Action::Select => vec![Mouse(MouseButton::Left)],
+            Action::SpawnLight => vec![Mouse(MouseButton::Right)],
+            Action::DragPan => vec![Mouse(MouseButton::Middle), Mouse(MouseButton::Right)],
+            Action::Delete => vec![Key(KeyCode::Delete)],
+            Action::CycleEmitter => vec![Key(KeyCode::E)],
+            Action::CycleShading => vec![Key(KeyCode::C), Gamepad(Pad::North)],
+            Action::ZoomIn => vec![Key(KeyCode::Equals), Gamepad(Pad::RightTrigger)],
+            Action::ZoomOut => vec![Key(KeyCode::Minus), Gamepad(Pad::LeftTrigger)],
+            Action::ResetZoom => vec![Key(KeyCode::Key0), Gamepad(Pad::RightThumb)],
+            Action::PanLeft => vec![Key(KeyCode::Left), Gamepad(Pad::DPadLeft)],
+            Action::PanRight => vec![Key(KeyCode::Right), Gamepad(Pad::DPadRight)],
+            Action::PanUp => vec![Key(KeyCode::Up), Gamepad(Pad::DPadUp)],
+            Action::PanDown => vec![Key(KeyCode::Down), Gamepad(Pad::DPadDown)],
+            Action::PlayPause => vec![Key(KeyCode::Space), Gamepad(Pad::Start)],
+            Action::StepBack => vec![Key(KeyCode::Comma), Gamepad(Pad::LeftTrigger2)],
+            Action::StepForward => vec![Key(KeyCode::Period), Gamepad(Pad::RightTrigger2)],
+            Action::Rewind => vec![Key(KeyCode::Home)],
+            Action::CyclePath => vec![Key(KeyCode::P)],
+            Action::ToggleMirror => vec![Key(KeyCode::M)],
+            Action::CycleBounceDepth => vec![Key(KeyCode::B)],
+            Action::CycleTransmittance => vec![Key(KeyCode::T)],
+            Action::AddVisitor => vec![Key(KeyCode::V)],
+            Action::AddCamera => vec![Key(KeyCode::K)],
+            Action::RotateCamera => vec![Key(KeyCode::R)],
+            Action::ToggleSurveillance => vec![Key(KeyCode::S), Gamepad(Pad::West)],
+            Action::ToggleProbe => vec![Key(KeyCode::I)],
+            Action::Solo => vec![Key(KeyCode::O)],
+            Action::Mute => vec![Key(KeyCode::X)],
+            Action::Ruler => vec![Key(KeyCode::L)],
+            Action::PinDimension => vec![Key(KeyCode::N)],
+            Action::ToggleHelp => vec![Key(KeyCode::F1), Gamepad(Pad::Select)],
+        };
+        Self(
+            Action::ALL
+                .into_iter()
+                .map(|action| (action, bindings(action)))
+                .collect(),
+        )
+    }
+}
+
+impl From<BTreeMap<Action, Vec<Binding>>> for Bindings {
+    fn from(saved: BTreeMap<Action, Vec<Binding>>) -> Self {
+        let mut bindings = Self::default();
+        bindings.0.extend(saved);
+        bindings
+    }
+}
+
+impl From<Bindings> for BTreeMap<Action, Vec<Binding>> {
+    fn from(bindings: Bindings) -> Self {
+        bindings.0
+    }
+}
+
+impl Bindings {
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.0.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn get_mut(&mut self, action: Action) -> &mut Vec<Binding> {
+        self.0.entry(action).or_default()
+    }
+
+    pub fn describe(&self, action: Action) -> String {
+        let labels: Vec<String> = self.get(action).iter().map(Binding::label).collect();
+        if labels.is_empty() {
+            "unbound".to_string()
+        } else {
+            labels.join(", ")
+        }
+    }
+}
+
+/// The actions held, started and ended this frame.
+#[derive(Resource, Default)]
+pub struct Actions {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+    just_released: HashSet<Action>,
+}
+
+impl Actions {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn just_released(&self, action: Action) -> bool {
+        self.just_released.contains(&action)
+    }
+
+    /// Keeps a press from reaching the systems that run after this one.
+    pub fn consume(&mut self, action: Action) {
+        self.pressed.remove(&action);
+        self.just_pressed.remove(&action);
+    }
+}
+
+pub fn read_actions(
+    settings: Res<Settings>,
+    keys: Res<Input<KeyCode>>,
+    mouse_button: Res<Input<MouseButton>>,
+    gamepad_button: Res<Input<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    mut actions: ResMut<Actions>,
+) {
+    let check = |binding: &Binding, state: &dyn Fn(&dyn InputState) -> bool| match *binding {
+        Binding::Key(key) => state(&(&*keys, key)),
+        Binding::Mouse(button) => state(&(&*mouse_button, button)),
+        Binding::Gamepad(button) => gamepads
+            .iter()
+            .any(|gamepad| state(&(&*gamepad_button, GamepadButton::new(gamepad, button)))),
+    };
+    let actions = &mut *actions;
+    for (set, state) in [
+        (
+            &mut actions.pressed,
+            &(|input: &dyn InputState| input.pressed()) as &dyn Fn(&dyn InputState) -> bool,
+        ),
+        (&mut actions.just_pressed, &|input| input.just_pressed()),
+        (&mut actions.just_released, &|input| input.just_released()),
+    ] {
+        set.clear();
+        set.extend(Action::ALL.into_iter().filter(|&action| {
+            settings
+                .bindings
+                .get(action)
+                .iter()
+                .any(|binding| check(binding, state))
+        }));
+    }
+}
+
+/// One binding's state, whatever kind of input it is.
+trait InputState {
+    fn pressed(&self) -> bool;
+    fn just_pressed(&self) -> bool;
+    fn just_released(&self) -> bool;
+}
+
+impl<T: Copy + Eq + std::hash::Hash + Send + Sync> InputState for (&Input<T>, T) {
+    fn pressed(&self) -> bool {
+        self.0.pressed(self.1)
+    }
+
+    fn just_pressed(&self) -> bool {
+        self.0.just_pressed(self.1)
+    }
+
+    fn just_released(&self) -> bool {
+        self.0.just_released(self.1)
+    }
+}
+
+/// Whether the controls overlay is shown.
+#[derive(Resource, Default)]
+pub struct ShowHelp(pub bool);
+
+pub fn toggle_help(actions: Res<Actions>, mut show: ResMut<ShowHelp>) {
+    if actions.just_pressed(Action::ToggleHelp) {
+        show.0 = !show.0;
+    }
+}
+
+/// Lists every action with its current bindings.
+pub fn help_overlay(
+    mut contexts: EguiContexts,
+    settings: Res<Settings>,
+    mut show: ResMut<ShowHelp>,
+) {
+    if !show.0 {
+        return;
+    }
+    let mut open = true;
+    egui::Window::new("Controls")
+        .open(&mut open)
+        .anchor(egui::Align2::RIGHT_TOP, [-8.0, 8.0])
+        .show(contexts.ctx_mut(), |ui| {
+            egui::Grid::new("controls").num_columns(2).show(ui, |ui| {
+                for action in Action::ALL {
+                    ui.label(action.description());
+                    ui.label(settings.bindings.describe(action));
+                    ui.end_row();
+                }
+            });
+        });
+    if !open {
+        show.0 = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_bindings_override_only_their_actions() {
+        let bindings: Bindings =
+            toml::from_str("delete = [{ Key = \"Back\" }, { Gamepad = \"East\" }]\nsolo = []\n")
+                .unwrap();
+        assert_eq!(
+            bindings.get(Action::Delete),
+            [
+                Binding::Key(KeyCode::Back),
+                Binding::Gamepad(GamepadButtonType::East)
+            ]
+        );
+        assert_eq!(bindings.describe(Action::Solo), "unbound");
+        assert_eq!(bindings.get(Action::Mute), [Binding::Key(KeyCode::X)]);
+        assert_eq!(
+            bindings.describe(Action::DragPan),
+            "Middle mouse, Right mouse"
+        );
+    }
+}
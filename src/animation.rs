@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+
+use bevy::ecs as bevy_ecs;
+use bevy::prelude::*;
+
+use crate::actions::{Action, Actions};
+use crate::settings::Settings;
+use crate::{Draggable, Dragging, Light, Selected, Theta, COLOR_PATH, PATH_Z};
+
+// 巻き戻し・早送りの幅 (秒)
+const SCRUB_STEP: f32 = 0.5;
+const PATH_POINT_SIZE: f32 = 8.0;
+const BEZIER_SEGMENTS: usize = 32;
+
+/// The animation clock. Lights are placed for `time` whenever it changes.
+#[derive(Resource, Default)]
+pub struct Timeline {
+    pub time: f32,
+    pub playing: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PathKind {
+    Polyline,
+    /// A single Bézier curve with the points as control points.
+    Bezier,
+}
+
+/// A route a light travels there and back in twice `period` seconds.
+#[derive(Component, Clone)]
+pub struct LightPath {
+    pub kind: PathKind,
+    pub points: Vec<Vec2>,
+    pub period: f32,
+}
+
+impl LightPath {
+    /// The point at `t` in `[0, 1]` along the path.
+    pub fn point_at(&self, t: f32) -> Vec2 {
+        match self.kind {
+            PathKind::Polyline => {
+                // 長さに比例して進む
+                let total: f32 = self.points.windows(2).map(|w| w[0].distance(w[1])).sum();
+                let mut remaining = t.clamp(0.0, 1.0) * total;
+                for w in self.points.windows(2) {
+                    let length = w[0].distance(w[1]);
+                    if remaining <= length && length > 0.0 {
+                        return w[0].lerp(w[1], remaining / length);
+                    }
+                    remaining -= length;
+                }
+                self.points.last().copied().unwrap_or_default()
+            }
+            PathKind::Bezier => {
+                let mut points = self.points.clone();
+                while points.len() > 1 {
+                    points = points.windows(2).map(|w| w[0].lerp(w[1], t)).collect();
+                }
+                points.first().copied().unwrap_or_default()
+            }
+        }
+    }
+
+    /// Where the light is at `time` seconds.
+    pub fn position(&self, time: f32) -> Vec2 {
+        let phase = (time / self.period).rem_euclid(2.0);
+        self.point_at(if phase > 1.0 { 2.0 - phase } else { phase })
+    }
+}
+
+/// Turns a light from its `Theta` direction by this many radians per second
+/// of the timeline, for motorised fixtures.
+#[derive(Component, Clone, Copy)]
+pub struct Spin(pub f32);
+
+/// Which way a light faces at `time`. Lights without a `Theta` face along x.
+pub fn facing(theta: Option<&Theta>, spin: Option<&Spin>, time: f32) -> f32 {
+    theta.map_or(0.0, |theta| theta.0) + spin.map_or(0.0, |spin| spin.0 * time)
+}
+
+/// A draggable control point of the path of `light`.
+#[derive(Component)]
+pub struct PathPoint {
+    pub light: Entity,
+    pub index: usize,
+}
+
+#[derive(Component)]
+pub struct TimelineLabel;
+
+pub fn control_timeline(actions: Res<Actions>, time: Res<Time>, mut timeline: ResMut<Timeline>) {
+    if actions.just_pressed(Action::PlayPause) {
+        timeline.playing = !timeline.playing;
+    }
+    if actions.just_pressed(Action::StepBack) {
+        timeline.time = (timeline.time - SCRUB_STEP).max(0.0);
+    }
+    if actions.just_pressed(Action::StepForward) {
+        timeline.time += SCRUB_STEP;
+    }
+    if actions.just_pressed(Action::Rewind) {
+        timeline.time = 0.0;
+    }
+    if timeline.playing {
+        timeline.time += time.delta_seconds();
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn animate_lights(
+    timeline: Res<Timeline>,
+    mut lights: Query<(&mut Transform, Ref<LightPath>), (With<Light>, Without<Dragging>)>,
+) {
+    for (mut transform, path) in lights.iter_mut() {
+        if timeline.is_changed() || path.is_changed() {
+            let position = path.position(timeline.time);
+            transform.translation = position.extend(transform.translation.z);
+        }
+    }
+}
+
+/// P gives the selected light a path, turns it into a curve, then removes it.
+#[allow(clippy::type_complexity)]
+pub fn cycle_light_path(
+    mut commands: Commands,
+    mut lights: Query<(Entity, &Transform, Option<&mut LightPath>), (With<Light>, With<Selected>)>,
+    actions: Res<Actions>,
+) {
+    if !actions.just_pressed(Action::CyclePath) {
+        return;
+    }
+    for (entity, transform, path) in lights.iter_mut() {
+        match path {
+            None => {
+                let start = transform.translation.truncate();
+                commands.entity(entity).insert(LightPath {
+                    kind: PathKind::Polyline,
+                    points: vec![
+                        start,
+                        start + Vec2::new(100.0, 100.0),
+                        start + Vec2::new(200.0, 0.0),
+                        start + Vec2::new(300.0, 100.0),
+                    ],
+                    period: 4.0,
+                });
+            }
+            Some(mut path) if path.kind == PathKind::Polyline => path.kind = PathKind::Bezier,
+            Some(_) => {
+                commands.entity(entity).remove::<LightPath>();
+            }
+        }
+    }
+}
+
+/// Keeps one draggable point per control point of every path, and writes
+/// moved points back into the path.
+pub fn sync_path_points(
+    mut commands: Commands,
+    mut paths: Query<(Entity, &mut LightPath)>,
+    points: Query<(Entity, &PathPoint, Ref<Transform>)>,
+) {
+    let mut counts: HashMap<Entity, usize> = HashMap::new();
+    for (entity, point, transform) in points.iter() {
+        let Ok((_, mut path)) = paths.get_mut(point.light) else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+        *counts.entry(point.light).or_default() += 1;
+        let position = transform.translation.truncate();
+        if transform.is_changed() && path.points.get(point.index) != Some(&position) {
+            if let Some(p) = path.points.get_mut(point.index) {
+                *p = position;
+            }
+        }
+    }
+    for (light, path) in paths.iter() {
+        if counts.get(&light).copied().unwrap_or(0) == path.points.len() {
+            continue;
+        }
+        for (entity, point, _) in points.iter() {
+            if point.light == light {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+        for (index, p) in path.points.iter().enumerate() {
+            commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: COLOR_PATH,
+                        ..default()
+                    },
+                    transform: Transform::from_translation(p.extend(PATH_Z))
+                        .with_scale(Vec2::splat(PATH_POINT_SIZE).extend(1.0)),
+                    ..default()
+                },
+                PathPoint { light, index },
+                Draggable,
+            ));
+        }
+    }
+}
+
+pub fn draw_light_paths(mut gizmos: Gizmos, paths: Query<&LightPath>) {
+    for path in paths.iter() {
+        match path.kind {
+            PathKind::Polyline => gizmos.linestrip_2d(path.points.iter().copied(), COLOR_PATH),
+            PathKind::Bezier => {
+                gizmos.linestrip_2d(path.points.iter().copied(), COLOR_PATH.with_a(0.3));
+                gizmos.linestrip_2d(
+                    (0..=BEZIER_SEGMENTS).map(|i| path.point_at(i as f32 / BEZIER_SEGMENTS as f32)),
+                    COLOR_PATH,
+                );
+            }
+        }
+    }
+}
+
+pub fn spawn_timeline_label(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 18.0,
+                color: Color::BLACK,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(8.0),
+            left: Val::Px(8.0),
+            ..default()
+        }),
+        TimelineLabel,
+    ));
+}
+
+pub fn update_timeline_label(
+    timeline: Res<Timeline>,
+    settings: Res<Settings>,
+    mut labels: Query<&mut Text, With<TimelineLabel>>,
+) {
+    if !timeline.is_changed() && !settings.is_changed() {
+        return;
+    }
+    let bindings = &settings.bindings;
+    for mut text in labels.iter_mut() {
+        text.sections[0].value = format!(
+            "{:.1} s {} ({}: play/pause, {} / {}: scrub, {}: rewind)",
+            timeline.time,
+            if timeline.playing {
+                "playing"
+            } else {
+                "paused"
+            },
+            bindings.describe(Action::PlayPause),
+            bindings.describe(Action::StepBack),
+            bindings.describe(Action::StepForward),
+            bindings.describe(Action::Rewind),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn light_goes_along_the_path_and_back() {
+        let mut path = LightPath {
+            kind: PathKind::Polyline,
+            points: vec![Vec2::ZERO, Vec2::new(100.0, 0.0), Vec2::new(100.0, 300.0)],
+            period: 4.0,
+        };
+        assert_eq!(path.position(0.0), Vec2::ZERO);
+        assert_eq!(path.position(1.0), Vec2::new(100.0, 0.0));
+        assert_eq!(path.position(4.0), Vec2::new(100.0, 300.0));
+        assert_eq!(path.position(7.0), Vec2::new(100.0, 0.0));
+        assert_eq!(path.position(8.0), Vec2::ZERO);
+
+        path.kind = PathKind::Bezier;
+        assert_eq!(path.position(0.0), Vec2::ZERO);
+        assert_eq!(path.position(2.0), Vec2::new(75.0, 75.0));
+        assert_eq!(path.position(4.0), Vec2::new(100.0, 300.0));
+    }
+}
@@ -0,0 +1,103 @@
+use std::f32::consts::TAU;
+
+use bevy::ecs as bevy_ecs;
+use bevy::prelude::*;
+use geo::{LineString, MultiPolygon, Polygon};
+
+use crate::spatial_index::LightReach;
+
+// 円弧の分割数
+const ARC_SEGMENTS: usize = 32;
+
+/// Limits a light to a cone `width` radians wide around the direction it
+/// faces, up to `range`. Lights without it shine everywhere.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct Beam {
+    pub width: f32,
+    pub range: f32,
+}
+
+impl Default for Beam {
+    fn default() -> Self {
+        Self {
+            width: TAU / 6.0,
+            range: 400.0,
+        }
+    }
+}
+
+impl Beam {
+    pub fn reach(&self, direction: f32) -> LightReach {
+        LightReach {
+            range: Some(self.range),
+            cone: Some((direction, self.width / 2.0)),
+        }
+    }
+
+    /// Whether a light at `light_position` facing `direction` shines on
+    /// `point`.
+    pub fn covers(&self, light_position: Vec2, direction: f32, point: Vec2) -> bool {
+        let ray = point - light_position;
+        if ray.length() > self.range {
+            return false;
+        }
+        self.width >= TAU
+            || ray == Vec2::ZERO
+            || Vec2::from_angle(direction).angle_between(ray).abs() <= self.width / 2.0
+    }
+
+    pub fn area(&self, light_position: Vec2, direction: f32) -> MultiPolygon<f32> {
+        sector(light_position, direction, self.width, self.range)
+    }
+}
+
+/// The sector `width` radians wide around `direction`, up to `range` from
+/// `position`. A full turn gives a disc.
+pub fn sector(position: Vec2, direction: f32, width: f32, range: f32) -> MultiPolygon<f32> {
+    let arc = |from: f32, to: f32| {
+        (0..=ARC_SEGMENTS).map(move |i| {
+            let angle = from + (to - from) * i as f32 / ARC_SEGMENTS as f32;
+            position + Vec2::from_angle(angle) * range
+        })
+    };
+    let points: Vec<Vec2> = if width >= TAU {
+        arc(0.0, TAU).skip(1).collect()
+    } else {
+        let half = width / 2.0;
+        std::iter::once(position)
+            .chain(arc(direction - half, direction + half))
+            .collect()
+    };
+    MultiPolygon::new(vec![Polygon::new(
+        LineString::from_iter(points.into_iter().map(|p| p.to_array())),
+        Vec::new(),
+    )])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::{Intersects, Point};
+    use std::f32::consts::FRAC_PI_2;
+
+    #[test]
+    fn beam_covers_its_sector() {
+        let beam = Beam {
+            width: FRAC_PI_2,
+            range: 100.0,
+        };
+        let light = Vec2::new(10.0, 10.0);
+        let area = beam.area(light, FRAC_PI_2);
+        for (offset, inside) in [
+            (Vec2::new(0.0, 50.0), true),
+            (Vec2::new(30.0, 50.0), true),
+            (Vec2::new(60.0, 50.0), false),
+            (Vec2::new(0.0, -50.0), false),
+            (Vec2::new(0.0, 120.0), false),
+        ] {
+            let point = light + offset;
+            assert_eq!(beam.covers(light, FRAC_PI_2, point), inside);
+            assert_eq!(area.intersects(&Point::new(point.x, point.y)), inside);
+        }
+    }
+}
@@ -0,0 +1,516 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use bevy::ecs as bevy_ecs;
+use bevy::prelude::*;
+use bevy::window::FileDragAndDrop;
+use bevy_egui::{egui, EguiContexts};
+use geo::{BoundingRect, Geometry, GeometryCollection, MultiLineString, Polygon};
+
+use crate::exchange::{ObstacleRecord, SceneFiles, SceneRecord};
+use crate::files;
+
+/// The units offered when the drawing doesn't say, in metres.
+const UNITS: [(&str, f64); 5] = [
+    ("mm", 0.001),
+    ("cm", 0.01),
+    ("m", 1.0),
+    ("in", 0.0254),
+    ("ft", 0.3048),
+];
+
+/// The shapes of a DXF drawing by layer, in drawing units.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CadDrawing {
+    /// From the drawing's `$INSUNITS`, unless it's unitless.
+    pub metres_per_unit: Option<f64>,
+    pub layers: BTreeMap<String, Vec<Geometry<f64>>>,
+}
+
+/// What a layer's shapes become.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LayerRole {
+    Skip,
+    /// Thin walls along every line and outline. They also set the room.
+    Walls,
+    /// Closed shapes are filled, open ones become walls.
+    #[default]
+    Obstacles,
+}
+
+impl LayerRole {
+    const ALL: [LayerRole; 3] = [LayerRole::Skip, LayerRole::Walls, LayerRole::Obstacles];
+
+    fn label(&self) -> &'static str {
+        match self {
+            LayerRole::Skip => "Skip",
+            LayerRole::Walls => "Walls",
+            LayerRole::Obstacles => "Obstacles",
+        }
+    }
+
+    /// A guess from the layer's name.
+    fn guess(layer: &str) -> Self {
+        let layer = layer.to_lowercase();
+        if layer.contains("wall") || layer.contains('壁') {
+            LayerRole::Walls
+        } else {
+            LayerRole::Obstacles
+        }
+    }
+}
+
+// DXF はネイティブでしか読まない
+#[cfg(not(target_arch = "wasm32"))]
+mod reader {
+    use super::*;
+    use dxf::entities::EntityType;
+    use geo::{Coord, LineString};
+    use std::f64::consts::TAU;
+
+    // 円一周をこの数の線分で近似する
+    const ARC_SEGMENTS: usize = 32;
+
+    /// Points along the arc around `center` from `start` turning `sweep`
+    /// radians, counterclockwise if positive.
+    fn arc(center: Coord<f64>, radius: f64, start: f64, sweep: f64) -> Vec<Coord<f64>> {
+        let segments = ((ARC_SEGMENTS as f64 * sweep.abs() / TAU).ceil() as usize).max(1);
+        (0..=segments)
+            .map(|i| {
+                let angle = start + sweep * i as f64 / segments as f64;
+                Coord {
+                    x: center.x + radius * angle.cos(),
+                    y: center.y + radius * angle.sin(),
+                }
+            })
+            .collect()
+    }
+
+    /// The points from `from` to `to` along a polyline segment, which is an arc
+    /// if `bulge`, the tangent of a quarter of its angle, isn't zero.
+    fn bulged(from: Coord<f64>, to: Coord<f64>, bulge: f64) -> Vec<Coord<f64>> {
+        let chord = to - from;
+        let length = chord.x.hypot(chord.y);
+        if bulge == 0.0 || length == 0.0 {
+            return vec![from, to];
+        }
+        let sweep = 4.0 * bulge.atan();
+        // 弦の中点から左へずらした点が中心
+        let offset = length / 2.0 / (sweep / 2.0).tan();
+        let left = Coord {
+            x: -chord.y / length,
+            y: chord.x / length,
+        };
+        let center = from + chord / 2.0 + left * offset;
+        let radius = (from - center).x.hypot((from - center).y);
+        let start = (from.y - center.y).atan2(from.x - center.x);
+        let mut points = arc(center, radius, start, sweep);
+        // 丸め誤差を残さない
+        *points.last_mut().unwrap() = to;
+        points
+    }
+
+    fn metres_per_unit(units: dxf::enums::Units) -> Option<f64> {
+        use dxf::enums::Units::*;
+        Some(match units {
+            Unitless => return None,
+            Inches => 0.0254,
+            Feet => 0.3048,
+            Miles => 1609.344,
+            Millimeters => 1e-3,
+            Centimeters => 1e-2,
+            Meters => 1.0,
+            Kilometers => 1e3,
+            Microinches => 2.54e-8,
+            Mils => 2.54e-5,
+            Yards => 0.9144,
+            Angstroms => 1e-10,
+            Nanometers => 1e-9,
+            Microns => 1e-6,
+            Decimeters => 0.1,
+            Decameters => 10.0,
+            Hectometers => 100.0,
+            Gigameters => 1e9,
+            AstronomicalUnits => 1.495_978_707e11,
+            LightYears => 9.460_730_472_580_8e15,
+            Parsecs => 3.085_677_581_49e16,
+            USSurveyFeet => 1200.0 / 3937.0,
+            USSurveyInch => 100.0 / 3937.0,
+            USSurveyYard => 3600.0 / 3937.0,
+            USSurveyMile => 6_336_000.0 / 3937.0,
+        })
+    }
+
+    /// The lines, polylines, circles and arcs of `drawing`. Anything else, and
+    /// anything in blocks, is left out.
+    pub(super) fn from_dxf(drawing: &dxf::Drawing) -> CadDrawing {
+        let coord = |x: f64, y: f64| Coord { x, y };
+        let mut layers: BTreeMap<String, Vec<Geometry<f64>>> = BTreeMap::new();
+        for entity in drawing.entities() {
+            let shape: Geometry<f64> = match &entity.specific {
+                EntityType::Line(line) => {
+                    geo::Line::new(coord(line.p1.x, line.p1.y), coord(line.p2.x, line.p2.y)).into()
+                }
+                EntityType::LwPolyline(polyline) => {
+                    let vertices = &polyline.vertices;
+                    let closed = polyline.is_closed() && vertices.len() > 2;
+                    let count = if closed {
+                        vertices.len()
+                    } else {
+                        vertices.len().saturating_sub(1)
+                    };
+                    let mut points = Vec::new();
+                    for i in 0..count {
+                        let (from, to) = (vertices[i], vertices[(i + 1) % vertices.len()]);
+                        let segment = bulged(coord(from.x, from.y), coord(to.x, to.y), from.bulge);
+                        points.extend_from_slice(&segment[usize::from(i > 0)..]);
+                    }
+                    let line = LineString::new(points);
+                    if closed {
+                        Polygon::new(line, Vec::new()).into()
+                    } else {
+                        line.into()
+                    }
+                }
+                EntityType::Circle(circle) => {
+                    let ring = arc(
+                        coord(circle.center.x, circle.center.y),
+                        circle.radius,
+                        0.0,
+                        TAU,
+                    );
+                    Polygon::new(LineString::new(ring), Vec::new()).into()
+                }
+                EntityType::Arc(entity) => {
+                    let start = entity.start_angle.to_radians();
+                    let sweep = (entity.end_angle.to_radians() - start).rem_euclid(TAU);
+                    let sweep = if sweep == 0.0 { TAU } else { sweep };
+                    LineString::new(arc(
+                        coord(entity.center.x, entity.center.y),
+                        entity.radius,
+                        start,
+                        sweep,
+                    ))
+                    .into()
+                }
+                _ => continue,
+            };
+            layers
+                .entry(entity.common.layer.clone())
+                .or_default()
+                .push(shape);
+        }
+        CadDrawing {
+            metres_per_unit: metres_per_unit(drawing.header.default_drawing_units),
+            layers,
+        }
+    }
+
+    pub(super) fn parse_drawing(bytes: &[u8]) -> Result<CadDrawing, String> {
+        dxf::Drawing::load(&mut &bytes[..])
+            .map(|drawing| from_dxf(&drawing))
+            .map_err(|error| error.to_string())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+use reader::parse_drawing;
+
+#[cfg(target_arch = "wasm32")]
+fn parse_drawing(_bytes: &[u8]) -> Result<CadDrawing, String> {
+    Err("DXF drawings aren't supported in the browser".to_string())
+}
+
+fn read_drawing(path: &Path) -> Result<CadDrawing, String> {
+    parse_drawing(&files::read(path)?)
+}
+
+/// The outlines of `shape`, so that it becomes walls instead of a block.
+fn outlines(shape: &Geometry<f64>) -> Geometry<f64> {
+    let rings = |polygon: &Polygon<f64>| {
+        std::iter::once(polygon.exterior().clone())
+            .chain(polygon.interiors().iter().cloned())
+            .collect::<Vec<_>>()
+    };
+    match shape {
+        Geometry::Polygon(polygon) => MultiLineString::new(rings(polygon)).into(),
+        Geometry::MultiPolygon(polygons) => {
+            MultiLineString::new(polygons.iter().flat_map(rings).collect()).into()
+        }
+        shape => shape.clone(),
+    }
+}
+
+/// The scene the layers make in their roles, in metres. The room is the
+/// extent of the walls, or of everything if there are none.
+pub fn to_scene(
+    drawing: &CadDrawing,
+    roles: &BTreeMap<String, LayerRole>,
+    metres_per_unit: f64,
+) -> SceneRecord {
+    let mut scene = SceneRecord::default();
+    let mut walls = Vec::new();
+    let mut everything = Vec::new();
+    for (layer, shapes) in &drawing.layers {
+        let role = roles.get(layer).copied().unwrap_or_default();
+        for shape in shapes {
+            let shape = match role {
+                LayerRole::Skip => continue,
+                LayerRole::Walls => outlines(shape),
+                LayerRole::Obstacles => shape.clone(),
+            };
+            let shape: Geometry<f64> = geo::MapCoords::map_coords(&shape, |c| c * metres_per_unit);
+            if role == LayerRole::Walls {
+                walls.push(shape.clone());
+            }
+            everything.push(shape.clone());
+            scene.obstacles.push(ObstacleRecord {
+                name: Some(layer.clone()),
+                shape,
+                transmittance: 0.0,
+                mirror: Vec::new(),
+            });
+        }
+    }
+    let extent = if walls.is_empty() { everything } else { walls };
+    scene.room = GeometryCollection::new_from(extent).bounding_rect();
+    scene
+}
+
+/// The DXF import window's state.
+#[derive(Resource)]
+pub struct CadEditor {
+    pub open: bool,
+    path: String,
+    drawing: Option<CadDrawing>,
+    roles: BTreeMap<String, LayerRole>,
+    metres_per_unit: f64,
+    message: Option<Result<String, String>>,
+}
+
+impl Default for CadEditor {
+    fn default() -> Self {
+        Self {
+            open: false,
+            path: String::new(),
+            drawing: None,
+            roles: BTreeMap::new(),
+            // 建築図面はたいてい mm
+            metres_per_unit: 0.001,
+            message: None,
+        }
+    }
+}
+
+impl CadEditor {
+    fn read(&mut self, path: &Path) {
+        match read_drawing(path) {
+            Ok(drawing) => {
+                self.roles = drawing
+                    .layers
+                    .keys()
+                    .map(|layer| (layer.clone(), LayerRole::guess(layer)))
+                    .collect();
+                if let Some(metres_per_unit) = drawing.metres_per_unit {
+                    self.metres_per_unit = metres_per_unit;
+                }
+                self.message = None;
+                self.drawing = Some(drawing);
+            }
+            Err(error) => {
+                self.message = Some(Err(error));
+                self.drawing = None;
+            }
+        }
+    }
+}
+
+fn is_dxf(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("dxf"))
+}
+
+pub fn cad_window(
+    mut contexts: EguiContexts,
+    mut editor: ResMut<CadEditor>,
+    mut files: SceneFiles,
+) {
+    if !editor.open {
+        return;
+    }
+    let editor = &mut *editor;
+    let mut open = true;
+    egui::Window::new("DXF import")
+        .open(&mut open)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut editor.path);
+                if ui.button("Open").clicked() {
+                    let path = std::path::PathBuf::from(editor.path.trim());
+                    editor.read(&path);
+                }
+            });
+            ui.label("A DXF file can also be dropped on the window.");
+
+            if let Some(drawing) = &editor.drawing {
+                ui.separator();
+                egui::Grid::new("cad_layers").num_columns(3).show(ui, |ui| {
+                    for (layer, shapes) in &drawing.layers {
+                        let Some(role) = editor.roles.get_mut(layer) else {
+                            continue;
+                        };
+                        ui.label(layer);
+                        ui.label(format!("{} shapes", shapes.len()));
+                        egui::ComboBox::from_id_source(("cad_layer", layer))
+                            .selected_text(role.label())
+                            .show_ui(ui, |ui| {
+                                for choice in LayerRole::ALL {
+                                    ui.selectable_value(role, choice, choice.label());
+                                }
+                            });
+                        ui.end_row();
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Drawing unit");
+                    let selected = UNITS
+                        .iter()
+                        .find(|(_, metres)| *metres == editor.metres_per_unit)
+                        .map_or_else(
+                            || format!("{} m", editor.metres_per_unit),
+                            |(name, _)| name.to_string(),
+                        );
+                    egui::ComboBox::from_id_source("cad_unit")
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            for (name, metres) in UNITS {
+                                ui.selectable_value(&mut editor.metres_per_unit, metres, name);
+                            }
+                        });
+                    if drawing.metres_per_unit.is_none() {
+                        ui.label("(the drawing doesn't say)");
+                    }
+                });
+                if ui.button("Import").clicked() {
+                    let scene = to_scene(drawing, &editor.roles, editor.metres_per_unit);
+                    editor.message = Some(Ok(format!(
+                        "Read {} shapes. The lights were kept.",
+                        scene.obstacles.len()
+                    )));
+                    files.load(scene, true);
+                }
+            }
+            match &editor.message {
+                Some(Ok(message)) => {
+                    ui.label(message);
+                }
+                Some(Err(error)) => {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+                None => {}
+            }
+        });
+    editor.open = open;
+}
+
+/// Opens DXF files dropped on the window for import.
+pub fn drop_drawing(mut events: EventReader<FileDragAndDrop>, mut editor: ResMut<CadEditor>) {
+    for event in events.iter() {
+        if let FileDragAndDrop::DroppedFile { path_buf, .. } = event {
+            if is_dxf(path_buf) {
+                editor.path = path_buf.display().to_string();
+                editor.read(path_buf);
+                editor.open = true;
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::reader::from_dxf;
+    use super::*;
+    use dxf::entities::{Arc, Circle, Entity, EntityType, Line, LwPolyline};
+    use dxf::{LwPolylineVertex, Point};
+
+    fn on_layer(specific: EntityType, layer: &str) -> Entity {
+        let mut entity = Entity::new(specific);
+        entity.common.layer = layer.to_string();
+        entity
+    }
+
+    #[test]
+    fn drawings_become_walls_and_obstacles_in_metres() {
+        let mut drawing = dxf::Drawing::new();
+        drawing.header.default_drawing_units = dxf::enums::Units::Millimeters;
+        drawing.add_entity(on_layer(
+            EntityType::Line(Line::new(
+                Point::new(0.0, 0.0, 0.0),
+                Point::new(8000.0, 0.0, 0.0),
+            )),
+            "A-WALL",
+        ));
+        let mut room = LwPolyline::default();
+        room.set_is_closed(true);
+        for (x, y, bulge) in [
+            (0.0, 0.0, 0.0),
+            (8000.0, 0.0, 0.0),
+            (8000.0, 6000.0, 1.0),
+            (0.0, 6000.0, 0.0),
+        ] {
+            room.vertices.push(LwPolylineVertex {
+                x,
+                y,
+                bulge,
+                ..default()
+            });
+        }
+        drawing.add_entity(on_layer(EntityType::LwPolyline(room), "A-WALL"));
+        drawing.add_entity(on_layer(
+            EntityType::Circle(Circle::new(Point::new(4000.0, 3000.0, 0.0), 500.0)),
+            "column",
+        ));
+        drawing.add_entity(on_layer(
+            EntityType::Arc(Arc::new(Point::new(0.0, 0.0, 0.0), 900.0, 0.0, 90.0)),
+            "door",
+        ));
+
+        let cad = from_dxf(&drawing);
+        assert_eq!(cad.metres_per_unit, Some(0.001));
+        assert_eq!(
+            cad.layers.keys().collect::<Vec<_>>(),
+            ["A-WALL", "column", "door"]
+        );
+        // 膨らみ 1 は半円
+        let Geometry::Polygon(outline) = &cad.layers["A-WALL"][1] else {
+            panic!("closed polylines are areas");
+        };
+        assert!(outline
+            .exterior()
+            .coords()
+            .any(|c| (c.x - 4000.0).abs() < 1e-6 && (c.y - 10000.0).abs() < 1e-6));
+        let Geometry::LineString(door) = &cad.layers["door"][0] else {
+            panic!("arcs are lines");
+        };
+        let end = door.0.last().unwrap();
+        assert!(end.x.abs() < 1e-9 && (end.y - 900.0).abs() < 1e-9);
+
+        let mut roles: BTreeMap<_, _> = cad
+            .layers
+            .keys()
+            .map(|layer| (layer.clone(), LayerRole::guess(layer)))
+            .collect();
+        assert_eq!(roles["A-WALL"], LayerRole::Walls);
+        roles.insert("door".to_string(), LayerRole::Skip);
+        let scene = to_scene(&cad, &roles, cad.metres_per_unit.unwrap());
+        assert_eq!(scene.obstacles.len(), 3);
+        let room = scene.room.unwrap();
+        assert!((room.width() - 8.0).abs() < 1e-9);
+        assert!((room.height() - 10.0).abs() < 1e-9);
+        assert!(matches!(
+            scene.obstacles[1].shape,
+            Geometry::MultiLineString(_)
+        ));
+        assert!(matches!(scene.obstacles[2].shape, Geometry::Polygon(_)));
+    }
+}
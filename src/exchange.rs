@@ -0,0 +1,781 @@
+use std::path::Path;
+
+use bevy::ecs as bevy_ecs;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::window::FileDragAndDrop;
+use bevy_egui::{egui, EguiContexts};
+use geo::{
+    BoundingRect, Coord, Geometry, GeometryCollection, Line, LineString, MapCoords, MultiPolygon,
+    Point, Polygon, Rect,
+};
+use geojson::{Feature, FeatureCollection, GeoJson, JsonObject, JsonValue};
+use wkt::{ToWkt, TryFromWkt};
+
+use crate::animation::Spin;
+use crate::beam::Beam;
+use crate::cad::CadEditor;
+use crate::files;
+use crate::illuminance::Luminaire;
+use crate::light_mix::LightColor;
+use crate::mirror::Mirror;
+use crate::settings::{from_hex, to_hex, Settings};
+use crate::shapes::{polygon_to_rects, spawn_obstacle, ObstacleRect};
+use crate::soft_shadow::Emitter;
+use crate::solo::Muted;
+use crate::translucency::Transmittance;
+use crate::{
+    calculate_vertices, spawn_light_at, Light, LightNumbers, Obstacle, SceneScale, ShadowGeometry,
+    Theta, WorldSize, WALL_THICKNESS,
+};
+
+// 書き出した長方形はそのまま長方形に戻す
+const RECT_FILL: f32 = 0.99;
+
+/// The layers of a WKT file, one geometry collection per line.
+const WKT_LAYERS: [&str; 4] = ["room", "obstacle", "light", "shadow"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObstacleRecord {
+    pub name: Option<String>,
+    pub shape: Geometry<f64>,
+    pub transmittance: f32,
+    /// The edges of the outline that reflect, edge `i` running from point
+    /// `i` to point `i + 1`.
+    pub mirror: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LightRecord {
+    pub name: Option<String>,
+    pub position: Point<f64>,
+    pub color: Option<Color>,
+    /// The brightness through the gel in the light mix.
+    pub gain: f32,
+    pub luminaire: Luminaire,
+    /// With its sizes in metres. Lights without one get the default disc.
+    pub emitter: Option<Emitter>,
+    pub theta: Option<Theta>,
+    /// With its range in metres.
+    pub beam: Option<Beam>,
+    /// Radians per second of the timeline.
+    pub spin: f32,
+    pub muted: bool,
+}
+
+/// A scene as exchanged with other tools, in metres.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SceneRecord {
+    pub room: Option<Rect<f64>>,
+    pub obstacles: Vec<ObstacleRecord>,
+    pub lights: Vec<LightRecord>,
+    /// Computed, so only ever exported.
+    pub shadows: Vec<(String, MultiPolygon<f64>)>,
+}
+
+impl SceneRecord {
+    /// Files the shape under the layer its kind names. Shapes without one
+    /// are lights if they're points and obstacles otherwise.
+    fn add(&mut self, kind: Option<&str>, shape: Geometry<f64>, properties: &JsonObject) {
+        let text = |key: &str| properties.get(key).and_then(JsonValue::as_str);
+        let number = |key: &str| properties.get(key).and_then(JsonValue::as_f64);
+        let numbers = |key: &str| -> Vec<f64> {
+            properties
+                .get(key)
+                .and_then(JsonValue::as_array)
+                .map_or(Vec::new(), |array| {
+                    array.iter().filter_map(JsonValue::as_f64).collect()
+                })
+        };
+        let name = text("name").map(str::to_string);
+        match (kind, shape) {
+            (Some("room"), shape) => self.room = shape.bounding_rect(),
+            (Some("shadow"), _) => {}
+            (Some("light") | None, Geometry::Point(position)) => self.lights.push(LightRecord {
+                name,
+                position,
+                color: text("color").and_then(|hex| from_hex(hex).ok()),
+                gain: number("gain").unwrap_or(1.0) as f32,
+                luminaire: {
+                    let default = Luminaire::default();
+                    Luminaire {
+                        intensity: number("candela").map_or(default.intensity, |n| n as f32),
+                        height: number("height").map_or(default.height, |n| n as f32),
+                    }
+                },
+                emitter: match text("emitter") {
+                    Some("point") => Some(Emitter::Point),
+                    Some("disc") => number("radius").map(|radius| Emitter::Disc(radius as f32)),
+                    Some("segment") => match numbers("half")[..] {
+                        [x, y] => Some(Emitter::Segment(Vec2::new(x as f32, y as f32))),
+                        _ => None,
+                    },
+                    _ => None,
+                },
+                theta: match numbers("theta")[..] {
+                    [direction, other] => Some(Theta(direction as f32, other as f32)),
+                    _ => None,
+                },
+                beam: match (number("width"), number("range")) {
+                    (Some(width), Some(range)) => Some(Beam {
+                        width: width as f32,
+                        range: range as f32,
+                    }),
+                    _ => None,
+                },
+                spin: number("spin").unwrap_or(0.0) as f32,
+                muted: properties.get("muted").and_then(JsonValue::as_bool) == Some(true),
+            }),
+            (Some("light") | None, Geometry::MultiPoint(points)) => {
+                for point in points {
+                    self.add(kind, Geometry::Point(point), properties);
+                }
+            }
+            (_, Geometry::GeometryCollection(collection)) => {
+                for shape in collection {
+                    self.add(kind, shape, properties);
+                }
+            }
+            (_, shape) => self.obstacles.push(ObstacleRecord {
+                name,
+                shape,
+                transmittance: number("transmittance").unwrap_or(0.0) as f32,
+                mirror: numbers("mirror").into_iter().map(|i| i as usize).collect(),
+            }),
+        }
+    }
+}
+
+fn feature(kind: &str, shape: &Geometry<f64>, mut properties: JsonObject) -> Feature {
+    properties.insert("kind".to_string(), kind.into());
+    Feature {
+        geometry: Some(geojson::Geometry::new(shape.into())),
+        properties: Some(properties),
+        ..default()
+    }
+}
+
+fn named(name: &Option<String>) -> JsonObject {
+    name.iter()
+        .map(|name| ("name".to_string(), name.as_str().into()))
+        .collect()
+}
+
+pub fn to_geojson(scene: &SceneRecord) -> String {
+    let room = scene
+        .room
+        .map(|room| feature("room", &room.to_polygon().into(), JsonObject::new()));
+    let obstacles = scene.obstacles.iter().map(|obstacle| {
+        let mut properties = named(&obstacle.name);
+        properties.insert("transmittance".to_string(), obstacle.transmittance.into());
+        if !obstacle.mirror.is_empty() {
+            properties.insert("mirror".to_string(), obstacle.mirror.clone().into());
+        }
+        feature("obstacle", &obstacle.shape, properties)
+    });
+    let lights = scene.lights.iter().map(|light| {
+        let mut properties = named(&light.name);
+        if let Some(color) = light.color {
+            properties.insert("color".to_string(), to_hex(color).into());
+        }
+        properties.insert("gain".to_string(), light.gain.into());
+        properties.insert("candela".to_string(), light.luminaire.intensity.into());
+        properties.insert("height".to_string(), light.luminaire.height.into());
+        match light.emitter {
+            Some(Emitter::Point) => {
+                properties.insert("emitter".to_string(), "point".into());
+            }
+            Some(Emitter::Disc(radius)) => {
+                properties.insert("emitter".to_string(), "disc".into());
+                properties.insert("radius".to_string(), radius.into());
+            }
+            Some(Emitter::Segment(half)) => {
+                properties.insert("emitter".to_string(), "segment".into());
+                properties.insert("half".to_string(), vec![half.x, half.y].into());
+            }
+            None => {}
+        }
+        if let Some(Theta(direction, other)) = light.theta {
+            properties.insert("theta".to_string(), vec![direction, other].into());
+        }
+        if let Some(beam) = light.beam {
+            properties.insert("width".to_string(), beam.width.into());
+            properties.insert("range".to_string(), beam.range.into());
+        }
+        if light.spin != 0.0 {
+            properties.insert("spin".to_string(), light.spin.into());
+        }
+        if light.muted {
+            properties.insert("muted".to_string(), true.into());
+        }
+        feature("light", &light.position.into(), properties)
+    });
+    let shadows = scene.shadows.iter().map(|(layer, region)| {
+        let properties = [("layer".to_string(), layer.as_str().into())];
+        feature(
+            "shadow",
+            &region.clone().into(),
+            properties.into_iter().collect(),
+        )
+    });
+    GeoJson::FeatureCollection(FeatureCollection {
+        bbox: None,
+        features: room
+            .into_iter()
+            .chain(obstacles)
+            .chain(lights)
+            .chain(shadows)
+            .collect(),
+        foreign_members: None,
+    })
+    .to_string()
+}
+
+pub fn from_geojson(text: &str) -> Result<SceneRecord, String> {
+    let features = match text.parse::<GeoJson>().map_err(|error| error.to_string())? {
+        GeoJson::FeatureCollection(collection) => collection.features,
+        GeoJson::Feature(feature) => vec![feature],
+        GeoJson::Geometry(geometry) => vec![Feature::from(geometry)],
+    };
+    let mut scene = SceneRecord::default();
+    for feature in features {
+        let Some(geometry) = &feature.geometry else {
+            continue;
+        };
+        let shape =
+            Geometry::<f64>::try_from(&geometry.value).map_err(|error| error.to_string())?;
+        let properties = feature.properties.clone().unwrap_or_default();
+        let kind = properties.get("kind").and_then(JsonValue::as_str);
+        scene.add(kind, shape, &properties);
+    }
+    Ok(scene)
+}
+
+/// One line per layer in the order of `WKT_LAYERS`. WKT has no properties,
+/// so names, colours and the fixtures' settings are left out.
+pub fn to_wkt(scene: &SceneRecord) -> String {
+    let layers: [Vec<Geometry<f64>>; 4] = [
+        scene
+            .room
+            .iter()
+            .map(|room| room.to_polygon().into())
+            .collect(),
+        scene
+            .obstacles
+            .iter()
+            .map(|obstacle| obstacle.shape.clone())
+            .collect(),
+        scene
+            .lights
+            .iter()
+            .map(|light| light.position.into())
+            .collect(),
+        scene
+            .shadows
+            .iter()
+            .map(|(_, region)| region.clone().into())
+            .collect(),
+    ];
+    layers
+        .into_iter()
+        .map(|shapes| GeometryCollection::new_from(shapes).wkt_string() + "\n")
+        .collect()
+}
+
+/// Reads the layers `to_wkt` writes. Lines with a single geometry instead are
+/// sorted by their type.
+pub fn from_wkt(text: &str) -> Result<SceneRecord, String> {
+    let mut scene = SceneRecord::default();
+    let lines = text.lines().filter(|line| !line.trim().is_empty());
+    for (index, line) in lines.enumerate() {
+        let shape = Geometry::<f64>::try_from_wkt_str(line).map_err(|error| error.to_string())?;
+        let kind = match shape {
+            Geometry::GeometryCollection(_) => WKT_LAYERS.get(index).copied(),
+            _ => None,
+        };
+        scene.add(kind, shape, &JsonObject::new());
+    }
+    Ok(scene)
+}
+
+fn is_wkt(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("wkt"))
+}
+
+pub fn is_scene_file(path: &Path) -> bool {
+    is_wkt(path)
+        || path.extension().is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("geojson") || extension.eq_ignore_ascii_case("json")
+        })
+}
+
+/// What reading and writing scene files needs.
+#[allow(clippy::type_complexity)]
+#[derive(SystemParam)]
+pub struct SceneFiles<'w, 's> {
+    commands: Commands<'w, 's>,
+    meshes: ResMut<'w, Assets<Mesh>>,
+    materials: ResMut<'w, Assets<ColorMaterial>>,
+    settings: Res<'w, Settings>,
+    scene_scale: Res<'w, SceneScale>,
+    world_size: ResMut<'w, WorldSize>,
+    light_numbers: ResMut<'w, LightNumbers>,
+    lights: Query<
+        'w,
+        's,
+        (
+            Entity,
+            Option<&'static Name>,
+            &'static mut Transform,
+            &'static LightColor,
+            Option<&'static Luminaire>,
+            Option<&'static Emitter>,
+            Option<&'static Theta>,
+            Option<&'static Beam>,
+            Option<&'static Spin>,
+            Option<&'static Muted>,
+        ),
+        With<Light>,
+    >,
+    obstacles: Query<
+        'w,
+        's,
+        (
+            Entity,
+            Option<&'static Name>,
+            &'static Transform,
+            Option<&'static Transmittance>,
+            Option<&'static Mirror>,
+        ),
+        With<Obstacle>,
+    >,
+    shadows: Query<'w, 's, &'static ShadowGeometry>,
+}
+
+impl SceneFiles<'_, '_> {
+    fn record(&self) -> SceneRecord {
+        let scale = f64::from(self.scene_scale.0);
+        let metres = |p: Vec2| Coord {
+            x: f64::from(p.x) * scale,
+            y: f64::from(p.y) * scale,
+        };
+        let (lower, upper) = self.world_size.boundary();
+        SceneRecord {
+            room: Some(Rect::new(metres(lower), metres(upper))),
+            obstacles: self
+                .obstacles
+                .iter()
+                .map(|(_, name, transform, transmittance, mirror)| {
+                    let corners = calculate_vertices(transform);
+                    let ring: LineString<f64> = corners
+                        .iter()
+                        .chain(corners.first())
+                        .map(|&p| metres(p))
+                        .collect();
+                    ObstacleRecord {
+                        name: name.map(|name| name.to_string()),
+                        shape: Polygon::new(ring, Vec::new()).into(),
+                        transmittance: transmittance.map_or(0.0, |t| t.0),
+                        mirror: mirror.map_or(Vec::new(), |mirror| {
+                            (0..4).filter(|&i| mirror.0[i]).collect()
+                        }),
+                    }
+                })
+                .collect(),
+            lights: self
+                .lights
+                .iter()
+                .map(
+                    |(_, name, transform, color, luminaire, emitter, theta, beam, spin, muted)| {
+                        LightRecord {
+                            name: name.map(|name| name.to_string()),
+                            position: metres(transform.translation.truncate()).into(),
+                            color: Some(color.color),
+                            gain: color.gain,
+                            luminaire: luminaire.copied().unwrap_or_default(),
+                            emitter: emitter.map(|&emitter| scale_emitter(emitter, scale as f32)),
+                            theta: theta.copied(),
+                            beam: beam.map(|&beam| Beam {
+                                range: beam.range * scale as f32,
+                                ..beam
+                            }),
+                            spin: spin.map_or(0.0, |spin| spin.0),
+                            muted: muted.is_some(),
+                        }
+                    },
+                )
+                .collect(),
+            shadows: self
+                .shadows
+                .iter()
+                .map(|shadow| {
+                    let region = shadow.region.map_coords(|c| metres(Vec2::new(c.x, c.y)));
+                    (shadow.layer.clone(), region)
+                })
+                .collect(),
+        }
+    }
+
+    /// Replaces the obstacles, the lights unless `keep_lights` is set and, if
+    /// the scene has one, the room. The room is moved to the origin along with
+    /// everything in it, kept lights included.
+    pub fn load(&mut self, scene: SceneRecord, keep_lights: bool) {
+        let scale = f64::from(self.scene_scale.0);
+        let center = scene.room.map_or(Coord::zero(), |room| room.center());
+        if let Some(room) = scene.room {
+            let size = Vec2::new(room.width() as f32, room.height() as f32);
+            self.world_size.0 = (size / self.scene_scale.0).max(Vec2::splat(10.0));
+        }
+        let world = |c: Coord<f64>| Coord {
+            x: ((c.x - center.x) / scale) as f32,
+            y: ((c.y - center.y) / scale) as f32,
+        };
+
+        if keep_lights {
+            let offset = Vec2::new(center.x as f32, center.y as f32) / self.scene_scale.0;
+            for (_, _, mut transform, ..) in self.lights.iter_mut() {
+                transform.translation -= offset.extend(0.0);
+            }
+        } else {
+            for (entity, ..) in self.lights.iter() {
+                self.commands.entity(entity).despawn_recursive();
+            }
+        }
+        for (entity, ..) in self.obstacles.iter() {
+            self.commands.entity(entity).despawn_recursive();
+        }
+
+        for light in scene.lights {
+            let position = world(light.position.0);
+            let name = light.name.unwrap_or_else(|| self.light_numbers.next_name());
+            let entity = spawn_light_at(
+                &mut self.commands,
+                &mut self.meshes,
+                &mut self.materials,
+                &self.settings,
+                Vec2::new(position.x, position.y),
+                name,
+            );
+            self.commands.entity(entity).insert(LightColor {
+                color: light.color.unwrap_or(Color::WHITE),
+                gain: light.gain,
+            });
+            self.commands.entity(entity).insert(light.luminaire);
+            if let Some(emitter) = light.emitter {
+                let emitter = scale_emitter(emitter, 1.0 / scale as f32);
+                self.commands.entity(entity).insert(emitter);
+            }
+            if let Some(theta) = light.theta {
+                self.commands.entity(entity).insert(theta);
+            }
+            if let Some(beam) = light.beam {
+                self.commands.entity(entity).insert(Beam {
+                    range: beam.range / scale as f32,
+                    ..beam
+                });
+            }
+            if light.spin != 0.0 {
+                self.commands.entity(entity).insert(Spin(light.spin));
+            }
+            if light.muted {
+                self.commands.entity(entity).insert(Muted);
+            }
+        }
+
+        for (index, obstacle) in scene.obstacles.into_iter().enumerate() {
+            let shape = obstacle.shape.map_coords(world);
+            let name = obstacle
+                .name
+                .unwrap_or_else(|| format!("Obstacle {}", index + 1));
+            let mirrors = outline_edges(&shape, &obstacle.mirror);
+            for rect in shape_to_rects(shape) {
+                let mirror = mirror_along(&rect, &mirrors);
+                let entity = spawn_obstacle(
+                    &mut self.commands,
+                    rect,
+                    self.settings.colors.obstacle,
+                    name.clone(),
+                );
+                if obstacle.transmittance > 0.0 {
+                    self.commands
+                        .entity(entity)
+                        .insert(Transmittance(obstacle.transmittance.min(1.0)));
+                }
+                if mirror.0.contains(&true) {
+                    self.commands.entity(entity).insert(mirror);
+                }
+            }
+        }
+    }
+
+    fn export(&self, path: &Path) -> Result<String, String> {
+        let scene = self.record();
+        let text = if is_wkt(path) {
+            to_wkt(&scene)
+        } else {
+            to_geojson(&scene)
+        };
+        files::write(path, &text)?;
+        Ok(format!(
+            "Wrote {} obstacles, {} lights and {} shadow layers.",
+            scene.obstacles.len(),
+            scene.lights.len(),
+            scene.shadows.len()
+        ))
+    }
+
+    fn import(&mut self, path: &Path) -> Result<String, String> {
+        let text = files::read_to_string(path)?;
+        let scene = if is_wkt(path) {
+            from_wkt(&text)?
+        } else {
+            from_geojson(&text)?
+        };
+        let message = format!(
+            "Read {} obstacles and {} lights.",
+            scene.obstacles.len(),
+            scene.lights.len()
+        );
+        self.load(scene, false);
+        Ok(message)
+    }
+}
+
+fn scale_emitter(emitter: Emitter, factor: f32) -> Emitter {
+    match emitter {
+        Emitter::Point => Emitter::Point,
+        Emitter::Disc(radius) => Emitter::Disc(radius * factor),
+        Emitter::Segment(half) => Emitter::Segment(half * factor),
+    }
+}
+
+/// The given edges of a polygon's outline.
+fn outline_edges(shape: &Geometry<f32>, edges: &[usize]) -> Vec<(Vec2, Vec2)> {
+    let Geometry::Polygon(polygon) = shape else {
+        return Vec::new();
+    };
+    let points: Vec<Vec2> = polygon
+        .exterior()
+        .points()
+        .map(|p| Vec2::new(p.x(), p.y()))
+        .collect();
+    edges
+        .iter()
+        .filter_map(|&i| Some((*points.get(i)?, *points.get(i + 1)?)))
+        .collect()
+}
+
+/// The edges of `rect` closest to and parallel with each of `mirrors`. Both
+/// faces of a wall built along a mirror edge reflect.
+fn mirror_along(rect: &ObstacleRect, mirrors: &[(Vec2, Vec2)]) -> Mirror {
+    let vertices = calculate_vertices(&rect.transform());
+    let mut mirror = Mirror::default();
+    for &(c, d) in mirrors {
+        let distances = (0..4).map(|i| {
+            let (a, b) = (vertices[i], vertices[(i + 1) % 4]);
+            let parallel = (b - a).normalize().perp_dot((d - c).normalize()).abs() < 1e-3;
+            let distance = (a + b).distance(c + d) / 2.0;
+            if parallel {
+                distance
+            } else {
+                f32::INFINITY
+            }
+        });
+        let distances: Vec<f32> = distances.collect();
+        let nearest = distances.iter().copied().fold(f32::INFINITY, f32::min);
+        // 壁なら中心線から厚さの半分ずれる
+        if nearest > WALL_THICKNESS / 2.0 + 1e-3 {
+            continue;
+        }
+        for (edge, distance) in mirror.0.iter_mut().zip(distances) {
+            *edge |= distance <= nearest + 1e-3;
+        }
+    }
+    mirror
+}
+
+/// Obstacles for any shape: areas as in `polygon_to_rects`, lines as walls.
+fn shape_to_rects(shape: Geometry<f32>) -> Vec<ObstacleRect> {
+    let walls = |line: LineString<f32>| {
+        line.lines()
+            .map(|Line { start, end }| {
+                ObstacleRect::along(
+                    Vec2::new(start.x, start.y),
+                    Vec2::new(end.x, end.y),
+                    WALL_THICKNESS,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    match shape {
+        Geometry::Polygon(polygon) => polygon_to_rects(&polygon, RECT_FILL, WALL_THICKNESS),
+        Geometry::MultiPolygon(polygons) => polygons
+            .iter()
+            .flat_map(|polygon| polygon_to_rects(polygon, RECT_FILL, WALL_THICKNESS))
+            .collect(),
+        Geometry::Rect(rect) => polygon_to_rects(&rect.to_polygon(), RECT_FILL, WALL_THICKNESS),
+        Geometry::Triangle(triangle) => {
+            polygon_to_rects(&triangle.to_polygon(), RECT_FILL, WALL_THICKNESS)
+        }
+        Geometry::Line(line) => walls(line.into()),
+        Geometry::LineString(line) => walls(line),
+        Geometry::MultiLineString(lines) => lines.into_iter().flat_map(walls).collect(),
+        Geometry::GeometryCollection(collection) => {
+            collection.into_iter().flat_map(shape_to_rects).collect()
+        }
+        Geometry::Point(_) | Geometry::MultiPoint(_) => Vec::new(),
+    }
+}
+
+/// The import and export window's state.
+#[derive(Resource, Default)]
+pub struct ExchangeEditor {
+    pub open: bool,
+    path: String,
+    message: Option<Result<String, String>>,
+}
+
+pub fn exchange_window(
+    mut contexts: EguiContexts,
+    mut editor: ResMut<ExchangeEditor>,
+    mut cad_editor: ResMut<CadEditor>,
+    mut files: SceneFiles,
+) {
+    if !editor.open {
+        return;
+    }
+    let editor = &mut *editor;
+    let mut open = true;
+    egui::Window::new("Import and export")
+        .open(&mut open)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.text_edit_singleline(&mut editor.path);
+            ui.label("GeoJSON, or WKT if the name ends in .wkt. Lengths are in metres.");
+            ui.horizontal(|ui| {
+                let path = Path::new(editor.path.trim());
+                if ui.button("Export").clicked() {
+                    editor.message = Some(files.export(path));
+                }
+                if ui.button("Import").clicked() {
+                    editor.message = Some(files.import(path));
+                }
+                if ui.button("DXF drawing…").clicked() {
+                    cad_editor.open = true;
+                }
+            });
+            match &editor.message {
+                Some(Ok(message)) => {
+                    ui.label(message);
+                }
+                Some(Err(error)) => {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+                None => {}
+            }
+        });
+    editor.open = open;
+}
+
+/// Imports GeoJSON and WKT files dropped on the window.
+pub fn drop_scene_file(
+    mut events: EventReader<FileDragAndDrop>,
+    mut editor: ResMut<ExchangeEditor>,
+    mut files: SceneFiles,
+) {
+    for event in events.iter() {
+        if let FileDragAndDrop::DroppedFile { path_buf, .. } = event {
+            if is_scene_file(path_buf) {
+                editor.path = path_buf.display().to_string();
+                editor.message = Some(files.import(path_buf));
+                editor.open = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::polygon;
+
+    #[test]
+    fn scenes_round_trip_through_geojson_and_wkt() {
+        let scene = SceneRecord {
+            room: Some(Rect::new((-10.0, -5.0), (10.0, 5.0))),
+            obstacles: vec![ObstacleRecord {
+                name: Some("Glass case".to_string()),
+                shape:
+                    polygon![(x: 0.0, y: 0.0), (x: 2.0, y: 0.0), (x: 2.0, y: 1.5), (x: 0.0, y: 1.5)]
+                        .into(),
+                transmittance: 0.5,
+                mirror: vec![0, 2],
+            }],
+            lights: vec![LightRecord {
+                name: Some("Spot".to_string()),
+                position: Point::new(-3.25, 1.0),
+                color: Some(Color::rgb_u8(255, 128, 0)),
+                gain: 2.0,
+                luminaire: Luminaire {
+                    intensity: 1500.0,
+                    height: 4.5,
+                },
+                emitter: Some(Emitter::Segment(Vec2::new(0.5, 0.25))),
+                theta: Some(Theta(1.5, 0.25)),
+                beam: Some(Beam {
+                    width: 0.75,
+                    range: 6.0,
+                }),
+                spin: -0.5,
+                muted: true,
+            }],
+            shadows: vec![(
+                "shadow of all lights, level 1".to_string(),
+                polygon![(x: 0.0, y: 0.0), (x: 1.0, y: 0.0), (x: 1.0, y: 1.0)].into(),
+            )],
+        };
+        let imported = SceneRecord {
+            shadows: Vec::new(),
+            ..from_geojson(&to_geojson(&scene)).unwrap()
+        };
+        assert_eq!(
+            imported,
+            SceneRecord {
+                shadows: Vec::new(),
+                ..scene.clone()
+            }
+        );
+
+        let imported = from_wkt(&to_wkt(&scene)).unwrap();
+        assert_eq!(imported.room, scene.room);
+        assert_eq!(imported.obstacles[0].shape, scene.obstacles[0].shape);
+        assert_eq!(imported.lights[0].position, scene.lights[0].position);
+        assert!(imported.shadows.is_empty());
+        assert!(imported.obstacles[0].mirror.is_empty());
+        assert_eq!(imported.lights[0].beam, None);
+
+        // 長方形に戻しても同じ辺が鏡になる
+        let shape = scene.obstacles[0].shape.map_coords(|c| Coord {
+            x: c.x as f32,
+            y: c.y as f32,
+        });
+        let mirrors = outline_edges(&shape, &scene.obstacles[0].mirror);
+        let rects = shape_to_rects(shape);
+        assert_eq!(rects.len(), 1);
+        let mirror = mirror_along(&rects[0], &mirrors);
+        let vertices = calculate_vertices(&rects[0].transform());
+        let mut reflecting: Vec<f32> = mirror
+            .edges(&vertices)
+            .map(|(a, b)| (a.y + b.y) / 2.0)
+            .collect();
+        reflecting.sort_by(f32::total_cmp);
+        assert_eq!(reflecting.len(), 2);
+        assert!(reflecting[0].abs() < 1e-3 && (reflecting[1] - 1.5).abs() < 1e-3);
+
+        // ほかのツールの素の図形は種類で振り分ける
+        let plain = from_wkt("POINT(1 2)\nLINESTRING(0 0,4 0)\n").unwrap();
+        assert_eq!(plain.lights.len(), 1);
+        assert_eq!(plain.obstacles.len(), 1);
+        assert_eq!(plain.room, None);
+    }
+}
@@ -0,0 +1,539 @@
+use std::collections::HashMap;
+
+use bevy::ecs as bevy_ecs;
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy_egui::{egui, EguiContexts};
+use geo::{Coord, LineString, Polygon, Simplify, Translate};
+
+use crate::floor_plan::FloorPlan;
+use crate::settings::Settings;
+use crate::shapes::{polygon_to_rects, spawn_obstacle, ObstacleRect};
+use crate::{
+    Obstacle, WorldSize, COLOR_CONTOUR, COLOR_TRACED, COLOR_WALL_MASK, EXTRACT_PREVIEW_DELAY,
+    WALL_THICKNESS,
+};
+
+// 大きな画像はこの大きさ (px) 程度に縮めてから調べる
+const WORKING_SIZE: u32 = 1024;
+
+/// Which pixels of the plan are walls, at the working resolution.
+pub struct Mask {
+    width: usize,
+    height: usize,
+    /// How many plan pixels one cell covers along each side.
+    step: usize,
+    cells: Vec<bool>,
+}
+
+impl Mask {
+    /// Marks the cells darker than `cutoff`, or lighter if `dark_walls` is
+    /// false. Transparent pixels count as white paper.
+    pub fn threshold(rgba: &[u8], size: UVec2, step: u32, cutoff: f32, dark_walls: bool) -> Self {
+        let step = step.max(1) as usize;
+        let (image_width, image_height) = (size.x as usize, size.y as usize);
+        let (width, height) = (image_width.div_ceil(step), image_height.div_ceil(step));
+        let mut cells = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let (mut sum, mut count) = (0.0, 0.0);
+                for py in y * step..((y + 1) * step).min(image_height) {
+                    for px in x * step..((x + 1) * step).min(image_width) {
+                        let i = (py * image_width + px) * 4;
+                        let [r, g, b, a] = [0, 1, 2, 3].map(|c| f32::from(rgba[i + c]) / 255.0);
+                        sum += (0.299 * r + 0.587 * g + 0.114 * b) * a + (1.0 - a);
+                        count += 1.0;
+                    }
+                }
+                let luminance = sum / count;
+                cells.push((luminance < cutoff) == dark_walls);
+            }
+        }
+        Self {
+            width,
+            height,
+            step,
+            cells,
+        }
+    }
+
+    fn get(&self, x: i32, y: i32) -> bool {
+        x >= 0
+            && y >= 0
+            && (x as usize) < self.width
+            && (y as usize) < self.height
+            && self.cells[y as usize * self.width + x as usize]
+    }
+
+    /// The mask as a translucent overlay image.
+    pub fn to_image(&self) -> Image {
+        let [r, g, b, a] = COLOR_WALL_MASK.as_rgba_u8();
+        let data = self
+            .cells
+            .iter()
+            .flat_map(|&wall| if wall { [r, g, b, a] } else { [0; 4] })
+            .collect();
+        Image::new(
+            Extent3d {
+                width: self.width as u32,
+                height: self.height as u32,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba8UnormSrgb,
+        )
+    }
+
+    /// The connected groups of wall cells with at least `min_cells` cells.
+    fn regions(&self, min_cells: usize) -> Vec<Vec<IVec2>> {
+        let mut seen = vec![false; self.cells.len()];
+        let mut regions = Vec::new();
+        for start in 0..self.cells.len() {
+            if !self.cells[start] || seen[start] {
+                continue;
+            }
+            seen[start] = true;
+            let mut stack = vec![start];
+            let mut region = Vec::new();
+            while let Some(i) = stack.pop() {
+                let cell = IVec2::new((i % self.width) as i32, (i / self.width) as i32);
+                region.push(cell);
+                for next in [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y].map(|d| cell + d) {
+                    if self.get(next.x, next.y) {
+                        let j = next.y as usize * self.width + next.x as usize;
+                        if !seen[j] {
+                            seen[j] = true;
+                            stack.push(j);
+                        }
+                    }
+                }
+            }
+            if region.len() >= min_cells {
+                regions.push(region);
+            }
+        }
+        regions
+    }
+}
+
+/// The outer boundary of a region along the cell edges, in cell corners.
+/// Holes are left out; they don't change the shadow.
+fn trace_outline(region: &[IVec2]) -> Vec<IVec2> {
+    let inside: std::collections::HashSet<IVec2> = region.iter().copied().collect();
+    // どのセルも同じ向きに回り、外に面した辺だけを集める
+    let mut edges: HashMap<IVec2, Vec<IVec2>> = HashMap::new();
+    for &cell in region {
+        let corners = [cell, cell + IVec2::X, cell + IVec2::ONE, cell + IVec2::Y];
+        for (side, neighbour) in [IVec2::NEG_Y, IVec2::X, IVec2::Y, IVec2::NEG_X]
+            .into_iter()
+            .enumerate()
+        {
+            if !inside.contains(&(cell + neighbour)) {
+                edges
+                    .entry(corners[side])
+                    .or_default()
+                    .push(corners[(side + 1) % 4]);
+            }
+        }
+    }
+    let mut loops = Vec::new();
+    while let Some(&start) = edges.keys().next() {
+        let mut outline = vec![start];
+        let mut at = start;
+        while let Some(next) = edges.get_mut(&at).and_then(Vec::pop) {
+            if edges[&at].is_empty() {
+                edges.remove(&at);
+            }
+            at = next;
+            outline.push(at);
+            if at == start {
+                break;
+            }
+        }
+        loops.push(outline);
+    }
+    let area = |outline: &Vec<IVec2>| {
+        outline
+            .windows(2)
+            .map(|edge| (edge[0].x * edge[1].y - edge[1].x * edge[0].y) as i64)
+            .sum::<i64>()
+            .abs()
+    };
+    let mut outline = loops.into_iter().max_by_key(area).unwrap_or_default();
+    // 直線の途中の点を除く。始点も角とは限らない
+    outline.pop();
+    let n = outline.len();
+    let mut corners: Vec<IVec2> = (0..n)
+        .filter(|&i| {
+            let (previous, here, next) =
+                (outline[(i + n - 1) % n], outline[i], outline[(i + 1) % n]);
+            here - previous != next - here
+        })
+        .map(|i| outline[i])
+        .collect();
+    if let Some(&first) = corners.first() {
+        corners.push(first);
+    }
+    corners
+}
+
+#[derive(Clone, PartialEq)]
+pub struct ExtractParams {
+    /// Luminance from 0 to 1 separating walls from the rest.
+    pub cutoff: f32,
+    pub dark_walls: bool,
+    /// Regions smaller than this many plan pixels are ignored as noise.
+    pub min_area: f32,
+    /// How far in plan pixels the simplified outline may stray.
+    pub tolerance: f32,
+    /// How much of its bounding rectangle a shape must fill to become one
+    /// rectangular obstacle instead of walls along its outline.
+    pub fill: f32,
+    /// The thickness of outline walls in world units.
+    pub thickness: f32,
+    pub fit_room: bool,
+    pub replace: bool,
+}
+
+impl Default for ExtractParams {
+    fn default() -> Self {
+        Self {
+            cutoff: 0.5,
+            dark_walls: true,
+            min_area: 64.0,
+            tolerance: 2.0,
+            fill: 0.85,
+            thickness: WALL_THICKNESS,
+            fit_room: true,
+            replace: false,
+        }
+    }
+}
+
+/// The outlines found on the plan, in the plan sprite's own coordinates.
+pub struct Outlines {
+    pub contours: Vec<Vec<Vec2>>,
+    pub polygons: Vec<Polygon<f32>>,
+}
+
+pub fn find_outlines(mask: &Mask, size: UVec2, params: &ExtractParams) -> Outlines {
+    let step = mask.step as f32;
+    let to_local = |corner: IVec2| {
+        Vec2::new(
+            corner.x as f32 * step - size.x as f32 / 2.0,
+            size.y as f32 / 2.0 - corner.y as f32 * step,
+        )
+    };
+    let min_cells = (params.min_area / (step * step)).ceil() as usize;
+    let contours: Vec<Vec<Vec2>> = mask
+        .regions(min_cells.max(1))
+        .iter()
+        .map(|region| trace_outline(region).into_iter().map(to_local).collect())
+        .collect();
+    let polygons = contours
+        .iter()
+        .map(|contour| {
+            let ring: LineString<f32> = contour.iter().map(|p| Coord { x: p.x, y: p.y }).collect();
+            Polygon::new(ring.simplify(&params.tolerance), Vec::new())
+        })
+        .filter(|polygon| polygon.exterior().0.len() >= 4)
+        .collect();
+    Outlines { contours, polygons }
+}
+
+/// The extraction window's state.
+#[derive(Resource, Default)]
+pub struct ExtractEditor {
+    pub open: bool,
+    params: ExtractParams,
+    /// The plan and the parameters the preview was made with.
+    previewed: Option<(Entity, ExtractParams)>,
+    outlines: Option<Outlines>,
+    error: Option<String>,
+}
+
+#[derive(Component)]
+pub struct MaskOverlay;
+
+fn map_polygon(polygon: &Polygon<f32>, transform: &Transform) -> Polygon<f32> {
+    let ring: LineString<f32> = polygon
+        .exterior()
+        .coords()
+        .map(|c| {
+            let p = transform.transform_point(Vec3::new(c.x, c.y, 0.0));
+            Coord { x: p.x, y: p.y }
+        })
+        .collect();
+    Polygon::new(ring, Vec::new())
+}
+
+/// Redoes the preview once the parameters have stopped changing for a
+/// moment, so dragging a slider doesn't trace the plan every frame.
+#[allow(clippy::too_many_arguments)]
+pub fn preview_extraction(
+    mut commands: Commands,
+    mut editor: ResMut<ExtractEditor>,
+    mut images: ResMut<Assets<Image>>,
+    time: Res<Time>,
+    plans: Query<(Entity, &Handle<Image>), With<FloorPlan>>,
+    overlays: Query<Entity, With<MaskOverlay>>,
+    mut settling: Local<Option<(ExtractParams, Timer)>>,
+) {
+    let plan = plans.get_single().ok();
+    if !editor.open || plan.is_none() {
+        for overlay in overlays.iter() {
+            commands.entity(overlay).despawn();
+        }
+        editor.previewed = None;
+        editor.outlines = None;
+        *settling = None;
+        return;
+    }
+    let Some((plan, texture)) = plan else {
+        return;
+    };
+    if editor.previewed.as_ref() == Some(&(plan, editor.params.clone())) {
+        *settling = None;
+        return;
+    }
+    // 図面が変わったときはすぐに作る
+    if editor.previewed.as_ref().map(|(previewed, _)| *previewed) == Some(plan) {
+        match settling.as_mut() {
+            Some((params, timer)) if *params == editor.params => {
+                if !timer.tick(time.delta()).finished() {
+                    return;
+                }
+            }
+            _ => {
+                let timer = Timer::from_seconds(EXTRACT_PREVIEW_DELAY, TimerMode::Once);
+                *settling = Some((editor.params.clone(), timer));
+                return;
+            }
+        }
+    }
+    *settling = None;
+    let Some(image) = images.get(texture) else {
+        return;
+    };
+    let size = image.size().as_uvec2();
+    // 8 ビットの RGBA 以外は読めない
+    if image.data.len() != (size.x * size.y * 4) as usize {
+        editor.error = Some("Only 8-bit images can be traced.".to_string());
+        editor.previewed = Some((plan, editor.params.clone()));
+        return;
+    }
+    let params = editor.params.clone();
+    let step = size.max_element().div_ceil(WORKING_SIZE);
+    let mask = Mask::threshold(&image.data, size, step, params.cutoff, params.dark_walls);
+    let outlines = find_outlines(&mask, size, &params);
+    let overlay = images.add(mask.to_image());
+    for overlay in overlays.iter() {
+        commands.entity(overlay).despawn();
+    }
+    let child = commands
+        .spawn((
+            SpriteBundle {
+                texture: overlay,
+                sprite: Sprite {
+                    custom_size: Some(size.as_vec2()),
+                    ..default()
+                },
+                transform: Transform::from_xyz(0.0, 0.0, 0.01),
+                ..default()
+            },
+            MaskOverlay,
+        ))
+        .id();
+    commands.entity(plan).add_child(child);
+    editor.error = None;
+    editor.outlines = Some(outlines);
+    editor.previewed = Some((plan, params));
+}
+
+pub fn draw_extraction(
+    editor: Res<ExtractEditor>,
+    plans: Query<&Transform, With<FloorPlan>>,
+    mut gizmos: Gizmos,
+) {
+    let (Some(outlines), Ok(transform)) = (&editor.outlines, plans.get_single()) else {
+        return;
+    };
+    let to_world = |p: Vec2| transform.transform_point(p.extend(0.0)).truncate();
+    for contour in &outlines.contours {
+        gizmos.linestrip_2d(contour.iter().map(|&p| to_world(p)), COLOR_CONTOUR);
+    }
+    for polygon in &outlines.polygons {
+        for rect in polygon_to_rects(
+            &map_polygon(polygon, transform),
+            editor.params.fill,
+            editor.params.thickness,
+        ) {
+            let corners = [
+                Vec2::new(-0.5, -0.5),
+                Vec2::new(0.5, -0.5),
+                Vec2::new(0.5, 0.5),
+                Vec2::new(-0.5, 0.5),
+                Vec2::new(-0.5, -0.5),
+            ]
+            .map(|corner| {
+                rect.transform()
+                    .transform_point(corner.extend(0.0))
+                    .truncate()
+            });
+            gizmos.linestrip_2d(corners, COLOR_TRACED);
+        }
+    }
+}
+
+fn slider(ui: &mut egui::Ui, label: &str, value: &mut f32, range: std::ops::RangeInclusive<f32>) {
+    ui.label(label);
+    ui.add(egui::Slider::new(value, range));
+    ui.end_row();
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn extraction_window(
+    mut contexts: EguiContexts,
+    mut commands: Commands,
+    mut editor: ResMut<ExtractEditor>,
+    mut plans: Query<&mut Transform, With<FloorPlan>>,
+    obstacles: Query<Entity, With<Obstacle>>,
+    mut world_size: ResMut<WorldSize>,
+    settings: Res<Settings>,
+) {
+    if !editor.open {
+        return;
+    }
+    let mut open = true;
+    let mut create = false;
+    let mut params = editor.params.clone();
+    egui::Window::new("Trace obstacles")
+        .open(&mut open)
+        .show(contexts.ctx_mut(), |ui| {
+            if plans.is_empty() {
+                ui.label("Load a floor plan first.");
+                return;
+            }
+            ui.heading("Threshold");
+            egui::Grid::new("threshold").num_columns(2).show(ui, |ui| {
+                slider(ui, "Cut-off", &mut params.cutoff, 0.0..=1.0);
+                ui.label("Walls are");
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut params.dark_walls, true, "dark");
+                    ui.radio_value(&mut params.dark_walls, false, "light");
+                });
+                ui.end_row();
+            });
+            ui.heading("Contours");
+            egui::Grid::new("contours").num_columns(2).show(ui, |ui| {
+                slider(
+                    ui,
+                    "Smallest region (px²)",
+                    &mut params.min_area,
+                    1.0..=10000.0,
+                );
+            });
+            ui.heading("Simplify");
+            egui::Grid::new("simplify").num_columns(2).show(ui, |ui| {
+                slider(ui, "Tolerance (px)", &mut params.tolerance, 0.0..=20.0);
+                slider(ui, "Rectangle fill", &mut params.fill, 0.5..=1.0);
+                slider(ui, "Wall thickness", &mut params.thickness, 1.0..=20.0);
+            });
+            if let Some(error) = &editor.error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+            if let Some(outlines) = &editor.outlines {
+                ui.label(format!("{} shapes found.", outlines.polygons.len()));
+            }
+            ui.separator();
+            ui.checkbox(&mut params.fit_room, "Fit the room to the shapes");
+            ui.checkbox(&mut params.replace, "Remove the current obstacles");
+            create = ui.button("Create obstacles").clicked();
+        });
+    if params != editor.params {
+        editor.params = params;
+    }
+    editor.open = open;
+    if !create {
+        return;
+    }
+    let (Some(outlines), Ok(mut transform)) = (editor.outlines.take(), plans.get_single_mut())
+    else {
+        return;
+    };
+    let params = &editor.params;
+    let mut polygons: Vec<Polygon<f32>> = outlines
+        .polygons
+        .iter()
+        .map(|polygon| map_polygon(polygon, &transform))
+        .collect();
+    let points: Vec<Vec2> = polygons
+        .iter()
+        .flat_map(|polygon| polygon.exterior().coords().map(|c| Vec2::new(c.x, c.y)))
+        .collect();
+    if params.fit_room && !points.is_empty() {
+        let lower = points.iter().copied().reduce(Vec2::min).unwrap_or_default();
+        let upper = points.iter().copied().reduce(Vec2::max).unwrap_or_default();
+        // 部屋は原点を中心にするので、図面ごと動かす
+        let center = (lower + upper) / 2.0;
+        transform.translation -= center.extend(0.0);
+        for polygon in polygons.iter_mut() {
+            polygon.translate_mut(-center.x, -center.y);
+        }
+        world_size.0 = (upper - lower).max(Vec2::splat(10.0));
+    }
+    if params.replace {
+        for obstacle in obstacles.iter() {
+            commands.entity(obstacle).despawn_recursive();
+        }
+    }
+    let rects: Vec<ObstacleRect> = polygons
+        .iter()
+        .flat_map(|polygon| polygon_to_rects(polygon, params.fill, params.thickness))
+        .collect();
+    for (index, rect) in rects.into_iter().enumerate() {
+        spawn_obstacle(
+            &mut commands,
+            rect,
+            settings.colors.obstacle,
+            format!("Traced {}", index + 1),
+        );
+    }
+    editor.open = false;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn traces_a_dark_block_on_white_paper() {
+        // 8×6 の白地に 4×2 の黒い壁
+        let size = UVec2::new(8, 6);
+        let mut rgba = vec![255u8; 8 * 6 * 4];
+        for y in 2..4 {
+            for x in 1..5 {
+                rgba[(y * 8 + x) * 4..][..3].fill(0);
+            }
+        }
+        rgba[(5 * 8 + 7) * 4..][..3].fill(0);
+        let mask = Mask::threshold(&rgba, size, 1, 0.5, true);
+        let params = ExtractParams {
+            min_area: 2.0,
+            tolerance: 0.1,
+            ..default()
+        };
+        let outlines = find_outlines(&mask, size, &params);
+        assert_eq!(outlines.contours.len(), 1);
+        assert_eq!(outlines.polygons.len(), 1);
+        let mut corners: Vec<(i32, i32)> = outlines.polygons[0]
+            .exterior()
+            .coords()
+            .map(|c| (c.x as i32, c.y as i32))
+            .collect();
+        corners.sort();
+        corners.dedup();
+        assert_eq!(corners, [(-3, -1), (-3, 1), (1, -1), (1, 1)]);
+    }
+}
@@ -0,0 +1,26 @@
+use std::path::Path;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read(path: &Path) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|error| error.to_string())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write(path: &Path, text: &str) -> Result<(), String> {
+    std::fs::write(path, text).map_err(|error| error.to_string())
+}
+
+// ブラウザにはファイルがない
+#[cfg(target_arch = "wasm32")]
+pub fn read(_path: &Path) -> Result<Vec<u8>, String> {
+    Err("files can't be read in the browser".to_string())
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn write(_path: &Path, _text: &str) -> Result<(), String> {
+    Err("files can't be written in the browser".to_string())
+}
+
+pub fn read_to_string(path: &Path) -> Result<String, String> {
+    String::from_utf8(read(path)?).map_err(|error| error.to_string())
+}
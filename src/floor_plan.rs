@@ -0,0 +1,322 @@
+use bevy::ecs as bevy_ecs;
+use bevy::prelude::*;
+use bevy::window::FileDragAndDrop;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::actions::{Action, Actions};
+use crate::extract::ExtractEditor;
+use crate::files;
+use crate::{SceneScale, WorldCoords, WorldSize, COLOR_RULER, PLAN_Z};
+
+/// A scanned floor plan shown over the floor to trace obstacles on. It can't
+/// be selected or dragged; it's placed with the calibration instead.
+#[derive(Component)]
+pub struct FloorPlan;
+
+/// The floor plan window's state.
+#[derive(Resource)]
+pub struct PlanEditor {
+    pub open: bool,
+    path: String,
+    error: Option<String>,
+    /// Collecting the two reference points when set.
+    calibrating: Option<Vec<Vec2>>,
+    /// The real distance between the reference points.
+    metres: f32,
+    /// The direction from the first reference point to the second.
+    degrees: f32,
+}
+
+impl Default for PlanEditor {
+    fn default() -> Self {
+        Self {
+            open: false,
+            path: String::new(),
+            error: None,
+            calibrating: None,
+            metres: 1.0,
+            degrees: 0.0,
+        }
+    }
+}
+
+/// Scales and turns `transform` about `from` so the line from `from` to `to`
+/// becomes `length` long and points at `angle`.
+pub fn calibrate(transform: Transform, from: Vec2, to: Vec2, length: f32, angle: f32) -> Transform {
+    let line = to - from;
+    if line.length() == 0.0 {
+        return transform;
+    }
+    let factor = length / line.length();
+    let turn = Quat::from_rotation_z(angle - line.y.atan2(line.x));
+    let pivot = from.extend(0.0);
+    let mut translation = pivot + turn * ((transform.translation - pivot) * factor);
+    translation.z = transform.translation.z;
+    Transform {
+        translation,
+        rotation: turn * transform.rotation,
+        scale: transform.scale * Vec3::new(factor, factor, 1.0),
+    }
+}
+
+fn read_image(path: &std::path::Path) -> Result<Image, String> {
+    use bevy::render::texture::{CompressedImageFormats, ImageType};
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .ok_or("the file has no extension")?;
+    let bytes = files::read(path)?;
+    Image::from_buffer(
+        &bytes,
+        ImageType::Extension(extension),
+        CompressedImageFormats::NONE,
+        true,
+    )
+    .map_err(|error| error.to_string())
+}
+
+/// Replaces the floor plan with the image at `path`, fitted to the room.
+fn open_plan(
+    commands: &mut Commands,
+    images: &mut Assets<Image>,
+    plans: &Query<Entity, With<FloorPlan>>,
+    world_size: Vec2,
+    path: &std::path::Path,
+) -> Result<(), String> {
+    let image = read_image(path)?;
+    let size = image.size();
+    for plan in plans.iter() {
+        commands.entity(plan).despawn_recursive();
+    }
+    let fit = (world_size / size).min_element();
+    commands.spawn((
+        SpriteBundle {
+            texture: images.add(image),
+            sprite: Sprite {
+                color: Color::WHITE.with_a(0.5),
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, 0.0, PLAN_Z).with_scale(Vec3::new(fit, fit, 1.0)),
+            ..default()
+        },
+        FloorPlan,
+        Name::new(path.display().to_string()),
+    ));
+    Ok(())
+}
+
+/// Opens images dropped on the window as the floor plan.
+pub fn drop_floor_plan(
+    mut commands: Commands,
+    mut events: EventReader<FileDragAndDrop>,
+    mut images: ResMut<Assets<Image>>,
+    plans: Query<Entity, With<FloorPlan>>,
+    world_size: Res<WorldSize>,
+    mut editor: ResMut<PlanEditor>,
+) {
+    for event in events.iter() {
+        if let FileDragAndDrop::DroppedFile { path_buf, .. } = event {
+            let is_image = path_buf.extension().is_some_and(|extension| {
+                ["png", "jpg", "jpeg"]
+                    .iter()
+                    .any(|image| extension.eq_ignore_ascii_case(image))
+            });
+            if !is_image {
+                continue;
+            }
+            editor.path = path_buf.display().to_string();
+            editor.error =
+                open_plan(&mut commands, &mut images, &plans, world_size.0, path_buf).err();
+            editor.open = true;
+        }
+    }
+}
+
+/// Collects the calibration's reference points. The clicks aren't passed on
+/// to the canvas.
+pub fn pick_calibration_points(
+    mut actions: ResMut<Actions>,
+    mut editor: ResMut<PlanEditor>,
+    cursor_position: Res<WorldCoords>,
+) {
+    let Some(points) = editor.calibrating.as_mut() else {
+        return;
+    };
+    if points.len() < 2 && actions.just_pressed(Action::Select) {
+        actions.consume(Action::Select);
+        points.push(cursor_position.0);
+    }
+}
+
+pub fn draw_calibration_points(editor: Res<PlanEditor>, mut gizmos: Gizmos) {
+    let Some(points) = &editor.calibrating else {
+        return;
+    };
+    for point in points {
+        gizmos.circle_2d(*point, 4.0, COLOR_RULER);
+    }
+    if let [from, to] = points[..] {
+        gizmos.line_2d(from, to, COLOR_RULER);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn floor_plan_window(
+    mut contexts: EguiContexts,
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut editor: ResMut<PlanEditor>,
+    mut extract_editor: ResMut<ExtractEditor>,
+    mut plans: Query<(&mut Transform, &mut Sprite), With<FloorPlan>>,
+    plan_entities: Query<Entity, With<FloorPlan>>,
+    world_size: Res<WorldSize>,
+    scene_scale: Res<SceneScale>,
+) {
+    if !editor.open {
+        editor.calibrating = None;
+        return;
+    }
+    let editor = &mut *editor;
+    let mut open = true;
+    egui::Window::new("Floor plan")
+        .open(&mut open)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut editor.path);
+                if ui.button("Load").clicked() {
+                    let path = std::path::PathBuf::from(editor.path.trim());
+                    editor.error = open_plan(
+                        &mut commands,
+                        &mut images,
+                        &plan_entities,
+                        world_size.0,
+                        &path,
+                    )
+                    .err();
+                }
+            });
+            ui.label("A PNG or JPEG image can also be dropped on the window.");
+            if let Some(error) = &editor.error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+
+            let Ok((mut transform, mut sprite)) = plans.get_single_mut() else {
+                return;
+            };
+            ui.separator();
+            egui::Grid::new("floor_plan").num_columns(2).show(ui, |ui| {
+                ui.label("Opacity");
+                let mut opacity = sprite.color.a();
+                if ui.add(egui::Slider::new(&mut opacity, 0.0..=1.0)).changed() {
+                    sprite.color.set_a(opacity);
+                }
+                ui.end_row();
+                ui.label("Position");
+                let mut position = transform.translation.truncate();
+                let changed = ui
+                    .horizontal(|ui| {
+                        ui.add(egui::DragValue::new(&mut position.x)).changed()
+                            | ui.add(egui::DragValue::new(&mut position.y)).changed()
+                    })
+                    .inner;
+                if changed {
+                    transform.translation = position.extend(PLAN_Z);
+                }
+                ui.end_row();
+            });
+
+            ui.separator();
+            match editor.calibrating.as_deref() {
+                None => {
+                    if ui.button("Calibrate").clicked() {
+                        editor.calibrating = Some(Vec::new());
+                    }
+                }
+                Some([from, to]) => {
+                    let (from, to) = (*from, *to);
+                    egui::Grid::new("calibration")
+                        .num_columns(2)
+                        .show(ui, |ui| {
+                            ui.label("Real distance (m)");
+                            ui.add(
+                                egui::DragValue::new(&mut editor.metres)
+                                    .speed(0.01)
+                                    .clamp_range(0.01..=f32::MAX),
+                            );
+                            ui.end_row();
+                            ui.label("Direction");
+                            ui.add(
+                                egui::DragValue::new(&mut editor.degrees)
+                                    .suffix("°")
+                                    .clamp_range(-180.0..=180.0),
+                            );
+                            ui.end_row();
+                        });
+                    ui.horizontal(|ui| {
+                        if ui.button("Apply").clicked() {
+                            *transform = calibrate(
+                                *transform,
+                                from,
+                                to,
+                                editor.metres / scene_scale.0,
+                                editor.degrees.to_radians(),
+                            );
+                            editor.calibrating = None;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            editor.calibrating = None;
+                        }
+                    });
+                }
+                Some(points) => {
+                    ui.label(format!(
+                        "Click two points on the plan a known distance apart ({}/2).",
+                        points.len()
+                    ));
+                    if ui.button("Cancel").clicked() {
+                        editor.calibrating = None;
+                    }
+                }
+            }
+
+            ui.separator();
+            if ui.button("Trace obstacles…").clicked() {
+                extract_editor.open = true;
+            }
+            if ui.button("Remove the plan").clicked() {
+                for plan in plan_entities.iter() {
+                    commands.entity(plan).despawn_recursive();
+                }
+                editor.calibrating = None;
+            }
+        });
+    editor.open = open;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calibration_maps_the_reference_line() {
+        let plan = Transform::from_xyz(10.0, 0.0, PLAN_Z);
+        let (from, to) = (Vec2::new(0.0, 0.0), Vec2::new(0.0, 20.0));
+        // 上向き 20 を右向き 100 にする
+        let calibrated = calibrate(plan, from, to, 100.0, 0.0);
+        let to_local = |transform: &Transform, point: Vec2| {
+            transform
+                .compute_matrix()
+                .inverse()
+                .transform_point3(point.extend(PLAN_Z))
+        };
+        let map = |point: Vec2| {
+            calibrated
+                .transform_point(to_local(&plan, point))
+                .truncate()
+        };
+        assert!(map(from).abs_diff_eq(from, 1e-4));
+        assert!(map(to).abs_diff_eq(Vec2::new(100.0, 0.0), 1e-3));
+        assert_eq!(calibrated.translation.z, PLAN_Z);
+        assert!((calibrated.scale.x - 5.0).abs() < 1e-5);
+    }
+}
@@ -77,8 +77,7 @@ pub trait ScaledBooleanOps {
     type Scalar: GeoNum;
     fn scaled_intersection(&self, other: &Self, scale: f32) -> MultiPolygon<Self::Scalar>;
     fn scaled_union(&self, other: &Self, scale: f32) -> MultiPolygon<Self::Scalar>;
-    fn xor(&self, other: &Self) -> MultiPolygon<Self::Scalar>;
-    fn difference(&self, other: &Self) -> MultiPolygon<Self::Scalar>;
+    fn scaled_difference(&self, other: &Self, scale: f32) -> MultiPolygon<Self::Scalar>;
 }
 
 impl ScaledBooleanOps for MultiPolygon<f32> {
@@ -99,10 +98,12 @@ impl ScaledBooleanOps for MultiPolygon<f32> {
             scale,
         )
     }
-    fn xor(&self, _other: &Self) -> MultiPolygon<Self::Scalar> {
-        unimplemented!()
-    }
-    fn difference(&self, _other: &Self) -> MultiPolygon<Self::Scalar> {
-        unimplemented!()
+    fn scaled_difference(&self, other: &Self, scale: f32) -> MultiPolygon<Self::Scalar> {
+        let p = self.to_integer_polygon(scale);
+        let q = other.to_integer_polygon(scale);
+        MultiPolygon::<f32>::from_integer_polygon(
+            &<MultiPolygon<f32> as BooleanOps>::difference(&p, &q),
+            scale,
+        )
     }
 }
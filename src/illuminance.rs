@@ -0,0 +1,197 @@
+use bevy::ecs as bevy_ecs;
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+
+use crate::raycast::{is_lit, transmission};
+
+/// Photometric data of a fixture hung above the floor plan.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct Luminaire {
+    /// Luminous intensity in candela, the same in every direction.
+    pub intensity: f32,
+    /// Height above the floor in metres.
+    pub height: f32,
+}
+
+impl Default for Luminaire {
+    fn default() -> Self {
+        Self {
+            intensity: 1000.0,
+            height: 3.0,
+        }
+    }
+}
+
+impl Luminaire {
+    /// Illuminance in lux on the floor at `offset` metres from the point
+    /// straight below the fixture: `I cos θ / d²`.
+    pub fn illuminance(&self, offset: Vec2) -> f32 {
+        let distance_2 = offset.length_squared() + self.height * self.height;
+        let cos_incidence = self.height / distance_2.sqrt();
+        self.intensity * cos_incidence / distance_2
+    }
+}
+
+/// Illuminance in lux sampled at the centre of square cells covering the room.
+#[derive(Clone)]
+pub struct IlluminanceGrid {
+    origin: Vec2,
+    cell: f32,
+    columns: usize,
+    rows: usize,
+    values: Vec<f32>,
+}
+
+impl IlluminanceGrid {
+    pub fn new(world_boundary: (Vec2, Vec2), cell: f32) -> Self {
+        let size = world_boundary.1 - world_boundary.0;
+        let columns = (size.x / cell).ceil() as usize;
+        let rows = (size.y / cell).ceil() as usize;
+        Self {
+            origin: world_boundary.0,
+            cell,
+            columns,
+            rows,
+            values: vec![0.0; columns * rows],
+        }
+    }
+
+    fn cell_center(&self, column: usize, row: usize) -> Vec2 {
+        self.origin + (Vec2::new(column as f32, row as f32) + 0.5) * self.cell
+    }
+
+    /// Adds the light of `luminaire` at `light_position` to every cell it can
+    /// see past `obstacles`, dimmed by the `translucent` obstacles in the way.
+    /// `metres_per_unit` converts world units to metres.
+    pub fn add_light(
+        &mut self,
+        light_position: Vec2,
+        luminaire: &Luminaire,
+        obstacles: &[[Vec2; 4]],
+        translucent: &[([Vec2; 4], f32)],
+        metres_per_unit: f32,
+    ) {
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                let center = self.cell_center(column, row);
+                if is_lit(light_position, center, obstacles) {
+                    self.values[row * self.columns + column] += luminaire
+                        .illuminance((center - light_position) * metres_per_unit)
+                        * transmission(light_position, center, translucent);
+                }
+            }
+        }
+    }
+
+    /// Darkens every cell whose centre fails `lit`.
+    pub fn retain(&mut self, lit: impl Fn(Vec2) -> bool) {
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                if !lit(self.cell_center(column, row)) {
+                    self.values[row * self.columns + column] = 0.0;
+                }
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: &IlluminanceGrid) {
+        for (value, other) in self.values.iter_mut().zip(&other.values) {
+            *value += other;
+        }
+    }
+
+    /// A texture with one pixel per cell, coloured by `color_map`.
+    pub fn to_image(&self) -> Image {
+        let mut data = Vec::with_capacity(self.values.len() * 4);
+        // 画像の行は上から並ぶ
+        for row in (0..self.rows).rev() {
+            for column in 0..self.columns {
+                let color = color_map(self.values[row * self.columns + column]);
+                data.extend(color.as_rgba_u8());
+            }
+        }
+        Image::new(
+            Extent3d {
+                width: self.columns as u32,
+                height: self.rows as u32,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba8UnormSrgb,
+        )
+    }
+
+    /// The size of the room the grid covers.
+    pub fn size(&self) -> Vec2 {
+        Vec2::new(self.columns as f32, self.rows as f32) * self.cell
+    }
+
+    pub fn center(&self) -> Vec2 {
+        self.origin + self.size() / 2.0
+    }
+}
+
+/// Maps illuminance on a log scale from 1 lux (black) to 1000 lux (pale
+/// yellow), through blue, red and orange.
+pub fn color_map(lux: f32) -> Color {
+    const STOPS: [(f32, [f32; 3]); 5] = [
+        (0.0, [0.0, 0.0, 0.02]),
+        (0.25, [0.2, 0.05, 0.45]),
+        (0.5, [0.75, 0.2, 0.35]),
+        (0.75, [0.98, 0.55, 0.05]),
+        (1.0, [0.99, 0.98, 0.65]),
+    ];
+    let t = (lux.max(1.0).log10() / 3.0).clamp(0.0, 1.0);
+    let upper = STOPS
+        .iter()
+        .position(|&(stop, _)| stop >= t)
+        .unwrap_or(4)
+        .max(1);
+    let (t0, c0) = STOPS[upper - 1];
+    let (t1, c1) = STOPS[upper];
+    let c = Vec3::from(c0).lerp(Vec3::from(c1), (t - t0) / (t1 - t0));
+    Color::rgb(c.x, c.y, c.z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value_at(grid: &IlluminanceGrid, point: Vec2) -> Option<f32> {
+        let cell = ((point - grid.origin) / grid.cell).floor();
+        let (column, row) = (cell.x as usize, cell.y as usize);
+        (cell.min_element() >= 0.0 && column < grid.columns && row < grid.rows)
+            .then(|| grid.values[row * grid.columns + column])
+    }
+
+    #[test]
+    fn inverse_square_and_cosine() {
+        let luminaire = Luminaire {
+            intensity: 900.0,
+            height: 3.0,
+        };
+        assert_eq!(luminaire.illuminance(Vec2::ZERO), 100.0);
+        // 距離 5 m、入射角の余弦 3/5
+        assert!((luminaire.illuminance(Vec2::new(4.0, 0.0)) - 900.0 * 0.6 / 25.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn blocked_cells_stay_dark() {
+        let world_boundary = (Vec2::new(-50.0, -50.0), Vec2::new(50.0, 50.0));
+        let wall = [
+            Vec2::new(10.0, -50.0),
+            Vec2::new(20.0, -50.0),
+            Vec2::new(20.0, 50.0),
+            Vec2::new(10.0, 50.0),
+        ];
+        let mut grid = IlluminanceGrid::new(world_boundary, 10.0);
+        grid.add_light(Vec2::ZERO, &Luminaire::default(), &[wall], &[], 0.1);
+
+        assert!(value_at(&grid, Vec2::new(-5.0, 0.0)).unwrap() > 0.0);
+        assert_eq!(value_at(&grid, Vec2::new(35.0, 0.0)), Some(0.0));
+        assert_eq!(value_at(&grid, Vec2::new(60.0, 0.0)), None);
+    }
+}
@@ -0,0 +1,1641 @@
+use bevy::ecs as bevy_ecs;
+use bevy::{
+    input::{
+        mouse::{MouseMotion, MouseWheel},
+        InputSystem,
+    },
+    prelude::*,
+    sprite::{collide_aabb, MaterialMesh2dBundle},
+    window::PrimaryWindow,
+};
+use bevy_egui::{EguiPlugin, EguiSet};
+use geo::{ConvexHull, Intersects, Line, LineString, MultiPoint, MultiPolygon, Polygon};
+
+mod actions;
+use actions::{help_overlay, read_actions, toggle_help, Action, Actions, ShowHelp};
+mod animation;
+pub use animation::Spin;
+use animation::{
+    animate_lights, control_timeline, cycle_light_path, draw_light_paths, facing,
+    spawn_timeline_label, sync_path_points, update_timeline_label, LightPath, PathKind, Timeline,
+};
+mod beam;
+pub use beam::Beam;
+mod cad;
+use cad::{cad_window, drop_drawing, CadEditor};
+mod exchange;
+use exchange::{drop_scene_file, exchange_window, ExchangeEditor};
+mod extract;
+use extract::{draw_extraction, extraction_window, preview_extraction, ExtractEditor};
+mod files;
+mod floor_plan;
+use floor_plan::{
+    draw_calibration_points, drop_floor_plan, floor_plan_window, pick_calibration_points,
+    PlanEditor,
+};
+mod geo_scaled;
+use geo_scaled::ScaledBooleanOps;
+mod illuminance;
+use illuminance::IlluminanceGrid;
+pub use illuminance::Luminaire;
+mod light_mix;
+use light_mix::calculate_light_mix;
+pub use light_mix::LightColor;
+mod measure;
+use measure::{draw_measurements, pin_dimension, toggle_ruler, use_ruler, Ruler};
+mod mirror;
+pub use mirror::Mirror;
+use mirror::{
+    calculate_reflections, cycle_bounce_depth, draw_mirror_edges, toggle_mirror_edge, BounceDepth,
+};
+mod panel;
+use panel::{capture_panel_input, scene_panel};
+mod polygon_mesh;
+use polygon_mesh::PolygonMeshBuilder;
+mod probe;
+use probe::{probe_cursor, spawn_probe_tooltip, toggle_probe, Probe};
+mod raycast;
+mod settings;
+mod shapes;
+pub use settings::Settings;
+use settings::{apply_palette, save_settings, settings_window, to_hex, ShowSettings};
+mod shadow_task;
+use shadow_task::ShadowTask;
+mod soft_shadow;
+pub use soft_shadow::Emitter;
+use soft_shadow::{calculate_soft_shadow, create_penumbra_mesh, unblocked_samples, SoftShadow};
+mod solo;
+pub use solo::Muted;
+use solo::{clear_removed_solo, solo_and_mute_selected, tint_muted_lights, Solo};
+mod spatial_index;
+use spatial_index::{update_obstacle_index, LightReach, ObstacleIndex};
+mod visitors;
+use visitors::{
+    add_visitor, spawn_visitor, spawn_visitor_report, track_visitor_light, update_visitor_report,
+    walk_visitors,
+};
+mod surveillance;
+use surveillance::{
+    add_camera, apply_surveillance, control_cameras, spawn_camera, sweep_cameras,
+    update_surveillance, ShowSurveillance, SurveillanceJob,
+};
+pub use surveillance::{Camera as SecurityCamera, Sweep};
+mod translucency;
+pub use translucency::Transmittance;
+use translucency::{
+    calculate_graded_shadow, cycle_obstacle_transmittance, shade, tint_translucent_obstacles,
+    GradedShadow,
+};
+
+const COLOR_NORMAL: Color = Color::ALICE_BLUE;
+const COLOR_SHADOW: Color = Color::GRAY;
+const COLOR_SHADOW_UNION: Color = Color::SILVER;
+const COLOR_SHADOW_INTERSECTION: Color = Color::GRAY;
+const COLOR_LIGHT: Color = Color::FUCHSIA;
+const COLOR_LIGHT_SELECTED: Color = Color::MIDNIGHT_BLUE;
+const COLOR_LIGHT_MUTED: Color = Color::rgb(0.45, 0.45, 0.5);
+const COLOR_OBSTACLE: Color = Color::DARK_GRAY;
+const COLOR_MIRROR: Color = Color::TURQUOISE;
+const COLOR_PATH: Color = Color::ORANGE;
+const COLOR_VISITOR_LIT: Color = Color::LIME_GREEN;
+const COLOR_VISITOR_DARK: Color = Color::MAROON;
+const COLOR_CAMERA: Color = Color::NAVY;
+const COLOR_COVERAGE: Color = Color::rgba(0.2, 0.4, 1.0, 0.25);
+const COLOR_BLIND_SPOT: Color = Color::rgba(1.0, 0.1, 0.1, 0.35);
+const COLOR_PROBE_CLEAR: Color = Color::YELLOW;
+const COLOR_PROBE_BLOCKED: Color = Color::RED;
+const COLOR_RULER: Color = Color::ORANGE_RED;
+const COLOR_DIMENSION: Color = Color::BLACK;
+const COLOR_WALL_MASK: Color = Color::rgba(1.0, 0.2, 0.2, 0.5);
+const COLOR_CONTOUR: Color = Color::rgba(0.8, 0.0, 0.8, 0.5);
+const COLOR_TRACED: Color = Color::DARK_GREEN;
+
+const WORLD_WIDTH: f32 = 960.0;
+const WORLD_HEIGHT: f32 = 720.0;
+
+const LIGHT_SIZE: f32 = 10.0;
+// 輪郭から作る壁の厚さ
+const WALL_THICKNESS: f32 = 4.0;
+// 押してからこれ以上動かしたらクリックではなくドラッグ
+const CLICK_SLOP: f32 = 4.0;
+// 大きいほど速く目標の倍率に近づく
+const ZOOM_EASING: f32 = 12.0;
+const ILLUMINANCE_CELL: f32 = 8.0;
+// 設定の変更が落ち着いてから保存するまでの秒数
+const SETTINGS_SAVE_DELAY: f32 = 1.0;
+// スライダーが止まってから輪郭を取り直すまでの秒数
+const EXTRACT_PREVIEW_DELAY: f32 = 0.3;
+
+const PATH_Z: f32 = 3.5;
+const LIGHT_Z: f32 = 3.0;
+const VISITOR_Z: f32 = 2.8;
+const MIRROR_Z: f32 = 2.5;
+const OBSTACLE_Z: f32 = 2.0;
+const SURVEILLANCE_Z: f32 = 1.5;
+const DARK_SHADOW_Z: f32 = 1.0;
+const PENUMBRA_Z: f32 = 0.75;
+const PALE_SHADOW_Z: f32 = 0.5;
+// 濃い影ほど上に重ねる
+const SHADE_LEVEL_Z: f32 = 0.01;
+// 床の上、影の下
+const PLAN_Z: f32 = 0.25;
+const BACKGROUND_Z: f32 = 0.0;
+
+/// The stages the simulation's systems run in each frame, for ordering an
+/// app's own systems around them.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MuseumSet {
+    /// Turns input into actions, in `PreUpdate`.
+    Input,
+    /// Spawning, selecting and dragging, the camera, tools and windows.
+    Edit,
+    /// Moves lights and visitors along their paths.
+    Animate,
+    /// Indexes the obstacles and computes the shadows (`update`).
+    Update,
+    /// Replaces the shadow meshes and the camera coverage with the results.
+    Apply,
+}
+
+/// The lighting simulation: lights, obstacles and their shadows, with
+/// dragging, the camera and the editing windows.
+///
+/// It uses the `Settings` resource if the app inserted one, and the defaults
+/// otherwise. Without `DefaultPlugins` it needs the assets plugin with
+/// meshes, colour materials and images, the input plugin and the gizmos.
+#[derive(Clone, Debug)]
+pub struct MuseumPlugin {
+    /// Spawns a 2D camera. Without it, the app's camera needs `CameraLabel`.
+    pub camera: bool,
+    /// Starts with the sample lights, obstacles, security camera and visitors.
+    pub sample_scene: bool,
+    /// The scene panel, the settings, the editor windows and the measurements,
+    /// all drawn with egui. Leave it off to run without a window.
+    pub ui: bool,
+    /// The size of the room in world units.
+    pub world_size: Vec2,
+    /// Metres per world unit.
+    pub scene_scale: f32,
+}
+
+impl Default for MuseumPlugin {
+    fn default() -> Self {
+        Self {
+            camera: true,
+            sample_scene: true,
+            ui: true,
+            world_size: Vec2::new(WORLD_WIDTH, WORLD_HEIGHT),
+            scene_scale: 0.02,
+        }
+    }
+}
+
+impl Plugin for MuseumPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Settings>()
+            .init_resource::<ShowSettings>()
+            .init_resource::<Actions>()
+            .init_resource::<ShowHelp>()
+            .insert_resource(WorldScale(1.0))
+            .init_resource::<Zoom>()
+            .init_resource::<DragPan>()
+            .init_resource::<LightNumbers>()
+            .insert_resource(WorldSize(self.world_size))
+            .insert_resource(SceneScale(self.scene_scale))
+            .init_resource::<WorldCoords>()
+            .init_resource::<ObstacleIndex>()
+            .init_resource::<ShadowJobs>()
+            .init_resource::<ShadingMode>()
+            .init_resource::<BounceDepth>()
+            .init_resource::<Timeline>()
+            .init_resource::<ShowSurveillance>()
+            .init_resource::<SurveillanceJob>()
+            .init_resource::<Probe>()
+            .init_resource::<Solo>()
+            .init_resource::<Ruler>()
+            .init_resource::<PlanEditor>()
+            .init_resource::<ExtractEditor>()
+            .init_resource::<ExchangeEditor>()
+            .init_resource::<CadEditor>()
+            .add_event::<MouseMotion>()
+            .configure_set(
+                PreUpdate,
+                MuseumSet::Input
+                    .after(InputSystem)
+                    .after(EguiSet::ProcessInput),
+            )
+            .configure_sets(
+                Update,
+                (
+                    MuseumSet::Edit,
+                    MuseumSet::Animate,
+                    MuseumSet::Update,
+                    MuseumSet::Apply,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                Startup,
+                (
+                    setup,
+                    spawn_timeline_label,
+                    spawn_visitor_report,
+                    spawn_probe_tooltip,
+                ),
+            )
+            .add_systems(PreUpdate, read_actions.in_set(MuseumSet::Input))
+            .add_systems(
+                Update,
+                (
+                    (spawn_light, despawn_selected_light, cycle_selected_emitter),
+                    (grab_object, drag_object, drop_object, unselect_object),
+                    (
+                        change_camera_scale,
+                        resize_floor,
+                        scale_world_with_scroll,
+                        ease_zoom,
+                        pan_with_drag,
+                        zoom_reset,
+                        screen_move,
+                    ),
+                    cursor_position_to_world_coordinate,
+                    toggle_shading_mode,
+                    (toggle_mirror_edge, cycle_bounce_depth, draw_mirror_edges),
+                    (cycle_obstacle_transmittance, tint_translucent_obstacles),
+                )
+                    .in_set(MuseumSet::Edit),
+            )
+            .add_systems(
+                Update,
+                (
+                    cycle_light_path,
+                    sync_path_points,
+                    draw_light_paths,
+                    update_timeline_label,
+                    add_visitor,
+                    update_visitor_report,
+                    add_camera,
+                    control_cameras,
+                    toggle_help,
+                    apply_palette,
+                    solo_and_mute_selected,
+                    clear_removed_solo,
+                    tint_muted_lights,
+                    (toggle_probe, probe_cursor).after(cursor_position_to_world_coordinate),
+                )
+                    .in_set(MuseumSet::Edit),
+            )
+            .add_systems(
+                Update,
+                (
+                    toggle_ruler,
+                    use_ruler
+                        .after(cursor_position_to_world_coordinate)
+                        .before(grab_object)
+                        .before(unselect_object),
+                    pin_dimension.after(use_ruler),
+                    pick_calibration_points
+                        .after(cursor_position_to_world_coordinate)
+                        .before(grab_object)
+                        .before(unselect_object),
+                    draw_calibration_points,
+                    (preview_extraction, draw_extraction).chain(),
+                )
+                    .in_set(MuseumSet::Edit),
+            )
+            .add_systems(
+                Update,
+                (
+                    control_timeline,
+                    animate_lights,
+                    walk_visitors,
+                    track_visitor_light,
+                )
+                    .chain()
+                    .in_set(MuseumSet::Animate),
+            )
+            .add_systems(
+                Update,
+                (update_obstacle_index, update)
+                    .chain()
+                    .in_set(MuseumSet::Update),
+            )
+            .add_systems(
+                Update,
+                (
+                    apply_shadows,
+                    (sweep_cameras, update_surveillance, apply_surveillance).chain(),
+                )
+                    .in_set(MuseumSet::Apply),
+            );
+
+        if self.camera {
+            app.add_systems(Startup, spawn_view_camera);
+        }
+        if self.sample_scene {
+            app.add_systems(Startup, spawn_sample_scene);
+        }
+        if self.ui {
+            if !app.is_plugin_added::<EguiPlugin>() {
+                app.add_plugins(EguiPlugin);
+            }
+            app.add_systems(
+                PreUpdate,
+                capture_panel_input
+                    .before(read_actions)
+                    .in_set(MuseumSet::Input),
+            )
+            .add_systems(
+                Update,
+                (
+                    scene_panel,
+                    draw_measurements,
+                    settings_window,
+                    help_overlay,
+                    drop_floor_plan,
+                    floor_plan_window,
+                    extraction_window.before(preview_extraction),
+                    (exchange_window, drop_scene_file, cad_window, drop_drawing),
+                )
+                    .in_set(MuseumSet::Edit),
+            )
+            // 閉じるフレームの AppExit も拾えるよう最後に回す
+            .add_systems(Last, save_settings);
+        }
+    }
+}
+
+/// The camera the simulation zooms and pans.
+#[derive(Component)]
+pub struct CameraLabel;
+
+#[derive(Component)]
+pub struct Light;
+
+#[derive(Component)]
+pub struct Obstacle;
+
+#[derive(Component)]
+pub struct Shadow;
+
+#[derive(Component)]
+pub struct Floor;
+
+/// The direction a light faces in radians, then a second angle that the
+/// scenes carry but nothing reads yet. `Beam` and `Spin` both work from the
+/// first.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct Theta(pub f32, pub f32);
+
+#[derive(Component)]
+pub struct Draggable;
+
+#[derive(Component)]
+pub struct Dragging;
+
+#[derive(Component)]
+pub struct Selected;
+
+#[derive(Resource, Default)]
+struct WorldCoords(Vec2);
+
+#[derive(Resource)]
+struct WorldScale(f32);
+
+/// The scale eased zooming is heading for, and the world point kept under
+/// the cursor on the way, with the cursor's offset from the window centre.
+#[derive(Resource)]
+struct Zoom {
+    target: f32,
+    anchor: Option<(Vec2, Vec2)>,
+}
+
+impl Default for Zoom {
+    fn default() -> Self {
+        Self {
+            target: 1.0,
+            anchor: None,
+        }
+    }
+}
+
+/// Where the cursor was last frame while panning.
+#[derive(Resource, Default)]
+struct DragPan {
+    last: Option<Vec2>,
+}
+
+/// How many lights have been numbered, so a new light never takes the name
+/// of one deleted before it.
+#[derive(Resource, Default)]
+struct LightNumbers(usize);
+
+impl LightNumbers {
+    fn next_name(&mut self) -> String {
+        self.0 += 1;
+        format!("Light {}", self.0)
+    }
+}
+
+/// The size of the room in world units, centred on the origin.
+#[derive(Resource, Clone, Copy)]
+pub struct WorldSize(pub Vec2);
+
+impl Default for WorldSize {
+    fn default() -> Self {
+        Self(Vec2::new(WORLD_WIDTH, WORLD_HEIGHT))
+    }
+}
+
+impl WorldSize {
+    fn boundary(&self) -> (Vec2, Vec2) {
+        (-self.0 / 2.0, self.0 / 2.0)
+    }
+}
+
+/// Metres per world unit.
+#[derive(Resource, Clone, Copy)]
+pub struct SceneScale(pub f32);
+
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ShadingMode {
+    /// Where some lights and where all lights are blocked.
+    #[default]
+    Shadows,
+    /// As `Shadows`, with lights as area emitters casting soft penumbrae.
+    SoftShadows,
+    /// The colour every region gets from the lights that reach it.
+    LightMix,
+    /// Illuminance on the floor in lux, colour-mapped.
+    Illuminance,
+}
+
+fn setup(mut commands: Commands, settings: Res<Settings>, world_size: Res<WorldSize>) {
+    // World
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: settings.colors.floor,
+                custom_size: Some(world_size.0),
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, 0.0, BACKGROUND_Z),
+            ..Default::default()
+        },
+        Floor,
+    ));
+}
+
+fn spawn_view_camera(mut commands: Commands) {
+    commands
+        .spawn(Camera2dBundle::default())
+        .insert(CameraLabel);
+}
+
+fn spawn_sample_scene(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut light_numbers: ResMut<LightNumbers>,
+    settings: Res<Settings>,
+) {
+    let light_size = settings.light_size;
+    let colors = &settings.colors;
+
+    // Circle
+    commands.spawn((
+        MaterialMesh2dBundle {
+            mesh: meshes.add(shape::Circle::new(1.0).into()).into(),
+            material: materials.add(ColorMaterial::from(colors.light)),
+            transform: Transform::from_translation(Vec3::new(400.0, 0.0, LIGHT_Z))
+                .with_scale(Vec3::new(light_size, light_size, 1.0)),
+            ..default()
+        },
+        Light,
+        Name::new(light_numbers.next_name()),
+        LightColor {
+            color: Color::rgb(1.0, 0.85, 0.6),
+            gain: 1.0,
+        },
+        Emitter::Disc(light_size),
+        Luminaire::default(),
+        LightPath {
+            kind: PathKind::Polyline,
+            points: vec![
+                Vec2::new(400.0, 0.0),
+                Vec2::new(400.0, 250.0),
+                Vec2::new(250.0, 300.0),
+            ],
+            period: 6.0,
+        },
+        Theta(0.0, 0.40),
+        Draggable,
+    ));
+
+    commands.spawn((
+        MaterialMesh2dBundle {
+            mesh: meshes.add(shape::Circle::new(1.0).into()).into(),
+            material: materials.add(ColorMaterial::from(colors.light)),
+            transform: Transform::from_translation(Vec3::new(-400.0, 0.0, LIGHT_Z))
+                .with_scale(Vec3::new(light_size, light_size, 1.0)),
+            ..default()
+        },
+        Light,
+        Name::new(light_numbers.next_name()),
+        LightColor {
+            color: Color::rgb(0.6, 0.8, 1.0),
+            gain: 1.0,
+        },
+        Emitter::Disc(light_size),
+        Luminaire::default(),
+        Theta(std::f32::consts::FRAC_PI_3, -0.35),
+        Draggable,
+    ));
+
+    // Quad
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: colors.obstacle,
+                ..default()
+            },
+            transform: Transform::from_translation(Vec3::new(0.0, -200.0, OBSTACLE_Z))
+                .with_scale(Vec3::new(60.0, 100.0, 1.0))
+                .with_rotation(Quat::from_rotation_z(0.0_f32.to_radians())),
+            ..default()
+        },
+        Obstacle,
+        Name::new("Plinth"),
+        Mirror([false, false, true, false]),
+    ));
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: colors.obstacle,
+                ..default()
+            },
+            transform: Transform::from_translation(Vec3::new(-50.0, 50.0, OBSTACLE_Z))
+                .with_scale(Vec3::new(10.0, 300.0, 1.0))
+                .with_rotation(Quat::from_rotation_z(-60.0_f32.to_radians())),
+            ..default()
+        },
+        Obstacle,
+        Name::new("Partition"),
+    ));
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: colors.obstacle,
+                ..default()
+            },
+            transform: Transform::from_translation(Vec3::new(-350.0, -250.0, OBSTACLE_Z))
+                .with_scale(Vec3::new(20.0, 70.0, 1.0))
+                .with_rotation(Quat::from_rotation_z(-45.0_f32.to_radians())),
+            ..default()
+        },
+        Obstacle,
+        Name::new("Pillar"),
+    ));
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: colors.obstacle.with_a(0.6),
+                ..default()
+            },
+            transform: Transform::from_translation(Vec3::new(200.0, 200.0, OBSTACLE_Z))
+                .with_scale(Vec3::new(80.0, 40.0, 1.0)),
+            ..default()
+        },
+        Obstacle,
+        Name::new("Glass case"),
+        Transmittance(0.5),
+    ));
+
+    // Security camera
+    spawn_camera(
+        &mut commands,
+        Vec2::new(-440.0, 320.0),
+        surveillance::Camera {
+            direction: -std::f32::consts::FRAC_PI_4,
+            fov: std::f32::consts::FRAC_PI_3,
+            range: 450.0,
+            sweep: Sweep::Panning {
+                amplitude: 0.4,
+                period: 8.0,
+            },
+        },
+    );
+
+    // Visitors
+    spawn_visitor(
+        &mut commands,
+        vec![Vec2::new(-420.0, 300.0), Vec2::new(420.0, -300.0)],
+    );
+    spawn_visitor(
+        &mut commands,
+        vec![
+            Vec2::new(-200.0, -320.0),
+            Vec2::new(100.0, -320.0),
+            Vec2::new(100.0, 0.0),
+        ],
+    );
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_light(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    actions: Res<Actions>,
+    cursor_position: Res<WorldCoords>,
+    q_window: Query<&Window, With<PrimaryWindow>>,
+    mut light_numbers: ResMut<LightNumbers>,
+    settings: Res<Settings>,
+    mut pressed_at: Local<Option<Vec2>>,
+) {
+    let cursor = q_window.get_single().ok().and_then(cursor_offset);
+    // パネルの上で押されたときは just_pressed が消されているので覚えない
+    if actions.just_pressed(Action::SpawnLight) {
+        *pressed_at = cursor;
+    }
+    if !actions.just_released(Action::SpawnLight) {
+        return;
+    }
+    let (Some(from), Some(to)) = (pressed_at.take(), cursor) else {
+        return;
+    };
+    if from.distance(to) < CLICK_SLOP {
+        spawn_light_at(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            &settings,
+            cursor_position.0,
+            light_numbers.next_name(),
+        );
+    }
+}
+
+/// A draggable disc light with the default size and colour, and everything
+/// `update` needs to cast its shadows.
+pub fn spawn_light_at(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+    settings: &Settings,
+    position: Vec2,
+    name: String,
+) -> Entity {
+    let light_size = settings.light_size;
+    commands
+        .spawn((
+            MaterialMesh2dBundle {
+                mesh: meshes.add(shape::Circle::new(1.0).into()).into(),
+                material: materials.add(ColorMaterial::from(settings.colors.light)),
+                transform: Transform::from_translation(position.extend(LIGHT_Z))
+                    .with_scale(Vec3::new(light_size, light_size, 1.0)),
+                ..default()
+            },
+            Light,
+            Name::new(name),
+            LightColor::default(),
+            Emitter::Disc(light_size),
+            Luminaire::default(),
+            Draggable,
+        ))
+        .id()
+}
+
+fn despawn_selected_light(
+    mut commands: Commands,
+    query: Query<Entity, With<Selected>>,
+    actions: Res<Actions>,
+) {
+    if actions.just_pressed(Action::Delete) {
+        for e in query.iter() {
+            commands.entity(e).despawn_recursive();
+        }
+    }
+}
+
+fn cycle_selected_emitter(
+    mut query: Query<&mut Emitter, With<Selected>>,
+    actions: Res<Actions>,
+    settings: Res<Settings>,
+) {
+    let light_size = settings.light_size;
+    if actions.just_pressed(Action::CycleEmitter) {
+        for mut emitter in query.iter_mut() {
+            *emitter = match *emitter {
+                Emitter::Point => Emitter::Disc(light_size),
+                Emitter::Disc(_) => Emitter::Segment(Vec2::new(light_size * 3.0, 0.0)),
+                Emitter::Segment(_) => Emitter::Point,
+            };
+        }
+    }
+}
+
+fn unselect_object(
+    mut commands: Commands,
+    query: Query<(Entity, &Transform), With<Selected>>,
+    actions: Res<Actions>,
+    cursor_position: Res<WorldCoords>,
+) {
+    if !actions.just_pressed(Action::Select) {
+        return;
+    }
+    let Ok((e, transform)) = query.get_single() else {
+        return;
+    };
+    if collide_aabb::collide(
+        cursor_position.0.extend(0.0),
+        [0.0, 0.0].into(),
+        transform.translation,
+        transform.scale.truncate() * 2.0,
+    )
+    .is_none()
+    {
+        unselect(&mut commands, e);
+    }
+}
+
+fn select(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+    e: Entity,
+    outline: Mesh,
+    color: Color,
+) {
+    let child = commands
+        .spawn((MaterialMesh2dBundle {
+            mesh: meshes.add(outline).into(),
+            material: materials.add(ColorMaterial::from(color)),
+            transform: Transform::from_scale(Vec3::new(1.3, 1.3, 1.0))
+                .with_translation(Vec3::new(0.0, 0.0, -0.1)),
+            ..Default::default()
+        },))
+        .id();
+    commands.entity(e).insert(Selected).add_child(child);
+}
+
+fn unselect(commands: &mut Commands, e: Entity) {
+    commands
+        .entity(e)
+        .despawn_descendants()
+        .clear_children()
+        .remove::<Selected>();
+}
+
+#[allow(clippy::too_many_arguments)]
+fn grab_object(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    draggable: Query<(Entity, &Transform), With<Draggable>>,
+    dragging: Query<&Dragging>,
+    actions: Res<Actions>,
+    cursor_position: Res<WorldCoords>,
+    settings: Res<Settings>,
+) {
+    if dragging.get_single().is_ok() || !actions.just_pressed(Action::Select) {
+        return;
+    }
+    for (e, transform) in draggable.iter() {
+        if collide_aabb::collide(
+            cursor_position.0.extend(0.0),
+            [0.0, 0.0].into(),
+            transform.translation,
+            transform.scale.truncate() * 2.0,
+        )
+        .is_some()
+        {
+            select(
+                &mut commands,
+                &mut meshes,
+                &mut materials,
+                e,
+                shape::Circle::new(1.0).into(),
+                settings.colors.light_selected,
+            );
+            commands.entity(e).insert(Dragging);
+            return;
+        }
+    }
+}
+
+fn drag_object(
+    mut object: Query<&mut Transform, With<Dragging>>,
+    actions: Res<Actions>,
+    cursor_position: Res<WorldCoords>,
+) {
+    if !actions.pressed(Action::Select) {
+        return;
+    }
+    let Ok(mut transform) = object.get_single_mut() else {
+        return;
+    };
+    transform.translation = cursor_position.0.extend(transform.translation.z);
+}
+
+fn drop_object(
+    mut commands: Commands,
+    object: Query<Entity, With<Dragging>>,
+    actions: Res<Actions>,
+) {
+    if actions.just_released(Action::Select) {
+        if let Ok(e) = object.get_single() {
+            commands.entity(e).remove::<Dragging>();
+        }
+    }
+}
+
+fn cursor_position_to_world_coordinate(
+    mut mycoords: ResMut<WorldCoords>,
+    q_window: Query<&Window, With<PrimaryWindow>>,
+    q_camera: Query<(&Camera, &GlobalTransform), With<CameraLabel>>,
+) {
+    let (Ok((camera, camera_transform)), Ok(window)) =
+        (q_camera.get_single(), q_window.get_single())
+    else {
+        return;
+    };
+    if let Some(world_position) = window
+        .cursor_position()
+        .and_then(|cursor| camera.viewport_to_world(camera_transform, cursor))
+        .map(|ray| ray.origin.truncate())
+    {
+        mycoords.0 = world_position;
+    }
+}
+
+/// The region a shadow entity covers, kept for export.
+#[derive(Component)]
+pub struct ShadowGeometry {
+    pub layer: String,
+    pub region: MultiPolygon<f32>,
+}
+
+#[derive(Resource, Default)]
+struct ShadowJobs {
+    outdated: bool,
+    mode: ShadingMode,
+    world_boundary: (Vec2, Vec2),
+    lights: Option<Vec<(ShadowTask<LightShadow>, Vec3)>>,
+    layers: Option<ShadowTask<Shading>>,
+}
+
+enum LightShadow {
+    /// The hard shadow, or the umbra and the penumbra for soft shadows.
+    Shadow(GradedShadow, Option<(Vec2, Mesh)>),
+    Illuminance(IlluminanceGrid),
+}
+
+struct ShadowLayers {
+    union: MultiPolygon<f32>,
+    intersection: Option<MultiPolygon<f32>>,
+}
+
+enum Shading {
+    /// The shadow layers for every shade level, and the penumbrae.
+    Shadows(Vec<(u8, ShadowLayers)>, Vec<(Vec2, Mesh)>),
+    LightMix(Vec<(MultiPolygon<f32>, Color)>),
+    Illuminance(IlluminanceGrid),
+}
+
+fn toggle_shading_mode(actions: Res<Actions>, mut mode: ResMut<ShadingMode>) {
+    if actions.just_pressed(Action::CycleShading) {
+        *mode = match *mode {
+            ShadingMode::Shadows => ShadingMode::SoftShadows,
+            ShadingMode::SoftShadows => ShadingMode::LightMix,
+            ShadingMode::LightMix => ShadingMode::Illuminance,
+            ShadingMode::Illuminance => ShadingMode::Shadows,
+        };
+    }
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn update(
+    mut jobs: ResMut<ShadowJobs>,
+    lights: Query<
+        (
+            Entity,
+            Ref<Transform>,
+            Ref<LightColor>,
+            Ref<Emitter>,
+            Ref<Luminaire>,
+            Option<Ref<Muted>>,
+            Option<Ref<Beam>>,
+            Option<Ref<Theta>>,
+            Option<Ref<Spin>>,
+        ),
+        With<Light>,
+    >,
+    timeline: Res<Timeline>,
+    mut removed_lights: RemovedComponents<Light>,
+    mut unmuted: RemovedComponents<Muted>,
+    mut removed_beams: RemovedComponents<Beam>,
+    solo: Res<Solo>,
+    mirrors: Query<(&Transform, Ref<Mirror>)>,
+    mut removed_mirrors: RemovedComponents<Mirror>,
+    obstacle_index: Res<ObstacleIndex>,
+    mode: Res<ShadingMode>,
+    scene_scale: Res<SceneScale>,
+    bounce_depth: Res<BounceDepth>,
+    world_size: Res<WorldSize>,
+    settings: Res<Settings>,
+) {
+    let light_removed = removed_lights.iter().count() > 0;
+    let light_unmuted = unmuted.iter().count() > 0;
+    let beam_removed = removed_beams.iter().count() > 0;
+    let mirror_removed = removed_mirrors.iter().count() > 0;
+    if light_removed
+        || light_unmuted
+        || beam_removed
+        || solo.is_changed()
+        || mirror_removed
+        || obstacle_index.is_changed()
+        || bounce_depth.is_changed()
+        || mirrors.iter().any(|(_, mirror)| mirror.is_changed())
+        || mode.is_changed()
+        || scene_scale.is_changed()
+        || world_size.is_changed()
+        || lights.iter().any(
+            |(_, transform, color, emitter, luminaire, muted, beam, theta, spin)| {
+                // 回っている光束は時間が進むたびに計算し直す
+                let turning = beam.is_some() && spin.is_some() && timeline.is_changed();
+                transform.is_changed()
+                    || color.is_changed()
+                    || emitter.is_changed()
+                    || luminaire.is_changed()
+                    || muted.is_some_and(|muted| muted.is_added())
+                    || beam.is_some_and(|beam| beam.is_changed())
+                    || theta.is_some_and(|theta| theta.is_changed())
+                    || spin.is_some_and(|spin| spin.is_changed())
+                    || turning
+            },
+        )
+    {
+        jobs.outdated = true;
+    }
+    // 計算中は前回の影を表示したまま、終わってから次を始める
+    if !jobs.outdated || jobs.lights.is_some() || jobs.layers.is_some() {
+        return;
+    }
+    jobs.outdated = false;
+    jobs.mode = *mode;
+    jobs.world_boundary = world_size.boundary();
+
+    let world_boundary = jobs.world_boundary;
+    let all_obstacles: Vec<[Vec2; 4]> = obstacle_index
+        .iter()
+        .map(|obstacle| obstacle.vertices)
+        .collect();
+    let mirror_edges: Vec<(Vec2, Vec2)> = mirrors
+        .iter()
+        .flat_map(|(transform, mirror)| {
+            mirror
+                .edges(&calculate_vertices(transform))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    let tasks = lights
+        .iter()
+        .filter(|(entity, .., muted)| solo.admits(*entity, muted.is_some()))
+        .map(
+            |(_, light, color, emitter, luminaire, _, beam, theta, spin)| {
+                let light_position = light.translation.truncate();
+                let direction = facing(theta.as_deref(), spin.as_deref(), timeline.time);
+                let beam = beam.map(|beam| *beam);
+                let reach = beam.map_or_else(LightReach::default, |beam| beam.reach(direction));
+                let samples = match *mode {
+                    ShadingMode::SoftShadows => emitter.samples(light_position),
+                    _ => vec![light_position],
+                };
+                // 面光源ではどこかの点から見える障害物をすべて使う
+                let mut visible: Vec<_> = samples
+                    .iter()
+                    .flat_map(|&sample| {
+                        obstacle_index.visible_from(sample, &reach, &world_boundary)
+                    })
+                    .collect();
+                visible.sort_by_key(|obstacle| obstacle.entity);
+                visible.dedup_by_key(|obstacle| obstacle.entity);
+                let (opaque, translucent): (Vec<_>, Vec<_>) = visible
+                    .into_iter()
+                    .partition(|obstacle| obstacle.transmittance == 0.0);
+                let obstacles: Vec<[Vec2; 4]> = opaque
+                    .into_iter()
+                    .map(|obstacle| obstacle.vertices)
+                    .collect();
+                let translucent: Vec<([Vec2; 4], f32)> = translucent
+                    .into_iter()
+                    .map(|obstacle| (obstacle.vertices, obstacle.transmittance))
+                    .collect();
+
+                let mode = *mode;
+                let luminaire = *luminaire;
+                let penumbra_color = settings.colors.shadow_union;
+                let metres_per_unit = scene_scale.0;
+                let depth = bounce_depth.0;
+                let all_obstacles = all_obstacles.clone();
+                let mirror_edges = mirror_edges.clone();
+                // 鏡で反射した光が届くところは影から除く
+                let reflected = move || {
+                    calculate_reflections(
+                        light_position,
+                        &all_obstacles,
+                        &mirror_edges,
+                        depth,
+                        world_boundary,
+                    )
+                };
+                // 光束の外は完全な影
+                let beyond_beam = move |shadow: GradedShadow| match beam {
+                    Some(beam) => shadow.union(
+                        &world_polygon(world_boundary)
+                            .scaled_difference(&beam.area(light_position, direction), 1e1),
+                    ),
+                    None => shadow,
+                };
+                let task = ShadowTask::spawn(move || match mode {
+                    ShadingMode::Illuminance => {
+                        let mut grid = IlluminanceGrid::new(world_boundary, ILLUMINANCE_CELL);
+                        grid.add_light(
+                            light_position,
+                            &luminaire,
+                            &obstacles,
+                            &translucent,
+                            metres_per_unit,
+                        );
+                        if let Some(beam) = beam {
+                            grid.retain(|point| beam.covers(light_position, direction, point));
+                        }
+                        LightShadow::Illuminance(grid)
+                    }
+                    ShadingMode::SoftShadows => {
+                        let samples = unblocked_samples(samples, &obstacles);
+                        let SoftShadow { umbra, penumbra } =
+                            calculate_soft_shadow(&samples, &obstacles, world_boundary);
+                        let penumbra =
+                            create_penumbra_mesh(&penumbra, &samples, &obstacles, penumbra_color);
+                        let shadow = calculate_graded_shadow(
+                            light_position,
+                            umbra,
+                            &translucent,
+                            world_boundary,
+                        );
+                        LightShadow::Shadow(beyond_beam(shadow.difference(&reflected())), penumbra)
+                    }
+                    ShadingMode::Shadows | ShadingMode::LightMix => {
+                        let shadow = calculate_graded_shadow(
+                            light_position,
+                            calculate_light_shadow(light_position, &obstacles, world_boundary),
+                            &translucent,
+                            world_boundary,
+                        );
+                        LightShadow::Shadow(beyond_beam(shadow.difference(&reflected())), None)
+                    }
+                });
+                (task, color.contribution())
+            },
+        )
+        .collect();
+    jobs.lights = Some(tasks);
+}
+
+fn apply_shadows(
+    mut commands: Commands,
+    mut jobs: ResMut<ShadowJobs>,
+    shadows: Query<Entity, With<Shadow>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+    settings: Res<Settings>,
+) {
+    let colors = &settings.colors;
+    if let Some(tasks) = &mut jobs.lights {
+        if !tasks.iter_mut().all(|(task, _)| task.is_finished()) {
+            return;
+        }
+        let light_shadows: Vec<(LightShadow, Vec3)> = jobs
+            .lights
+            .take()
+            .into_iter()
+            .flatten()
+            .filter_map(|(task, contribution)| Some((task.into_result()?, contribution)))
+            .collect();
+        let mode = jobs.mode;
+        let world_boundary = jobs.world_boundary;
+        jobs.layers = Some(ShadowTask::spawn(move || {
+            let mut shadows = Vec::new();
+            let mut penumbrae = Vec::new();
+            let mut contributions = Vec::new();
+            let mut grid = IlluminanceGrid::new(world_boundary, ILLUMINANCE_CELL);
+            for (light_shadow, contribution) in light_shadows {
+                match light_shadow {
+                    LightShadow::Shadow(shadow, penumbra) => {
+                        shadows.push(shadow);
+                        penumbrae.extend(penumbra);
+                        contributions.push(contribution);
+                    }
+                    LightShadow::Illuminance(light_grid) => grid.merge(&light_grid),
+                }
+            }
+            match mode {
+                ShadingMode::Shadows | ShadingMode::SoftShadows => {
+                    let mut levels: Vec<u8> =
+                        shadows.iter().flat_map(GradedShadow::levels).collect();
+                    levels.sort();
+                    levels.dedup();
+                    let layers = levels
+                        .into_iter()
+                        .map(|level| {
+                            let shadows = shadows.iter().map(|shadow| shadow.at_least(level));
+                            (level, calculate_shadow_layers(shadows.collect()))
+                        })
+                        .collect();
+                    Shading::Shadows(layers, penumbrae)
+                }
+                ShadingMode::LightMix => {
+                    let room = world_polygon(world_boundary);
+                    Shading::LightMix(calculate_light_mix(
+                        room.clone(),
+                        shadows
+                            .iter()
+                            .zip(contributions)
+                            .flat_map(|(shadow, contribution)| {
+                                shadow
+                                    .lit_areas(&room)
+                                    .into_iter()
+                                    .map(move |(area, share)| (area, contribution * share))
+                            }),
+                    ))
+                }
+                ShadingMode::Illuminance => Shading::Illuminance(grid),
+            }
+        }));
+    }
+
+    let Some(task) = &mut jobs.layers else {
+        return;
+    };
+    if !task.is_finished() {
+        return;
+    }
+    let Some(shading) = jobs.layers.take().and_then(ShadowTask::into_result) else {
+        return;
+    };
+
+    for entity in shadows.iter() {
+        commands.entity(entity).despawn();
+    }
+    let layers: Vec<(MultiPolygon<f32>, Color, f32, String)> = match shading {
+        Shading::Shadows(layers, penumbrae) => {
+            for (translation, mesh) in penumbrae {
+                commands.spawn((
+                    MaterialMesh2dBundle {
+                        mesh: meshes.add(mesh).into(),
+                        material: materials.add(ColorMaterial::from(Color::WHITE)),
+                        transform: Transform::from_translation(translation.extend(PENUMBRA_Z)),
+                        ..Default::default()
+                    },
+                    Shadow,
+                ));
+            }
+            layers
+                .into_iter()
+                .flat_map(|(level, layers)| {
+                    let z = level as f32 * SHADE_LEVEL_Z;
+                    std::iter::once((
+                        layers.union,
+                        shade(colors.floor, colors.shadow_union, level),
+                        PALE_SHADOW_Z + z,
+                        format!("shadow of some lights, level {level}"),
+                    ))
+                    .chain(layers.intersection.map(|shadow| {
+                        (
+                            shadow,
+                            shade(colors.shadow_union, colors.shadow_intersection, level),
+                            DARK_SHADOW_Z + z,
+                            format!("shadow of all lights, level {level}"),
+                        )
+                    }))
+                })
+                .collect()
+        }
+        Shading::LightMix(regions) => regions
+            .into_iter()
+            .map(|(region, color)| {
+                let layer = format!("light mix {}", to_hex(color));
+                (region, color, PALE_SHADOW_Z, layer)
+            })
+            .collect(),
+        Shading::Illuminance(grid) => {
+            commands.spawn((
+                SpriteBundle {
+                    texture: images.add(grid.to_image()),
+                    sprite: Sprite {
+                        custom_size: Some(grid.size()),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(grid.center().extend(PALE_SHADOW_Z)),
+                    ..Default::default()
+                },
+                Shadow,
+            ));
+            Vec::new()
+        }
+    };
+    for (polygon, color, z, layer) in layers {
+        let mut builder = PolygonMeshBuilder::default();
+        builder.add_multi_polygon(&polygon);
+        if let Some((translation, mesh)) = builder.build() {
+            commands.spawn((
+                MaterialMesh2dBundle {
+                    mesh: meshes.add(mesh).into(),
+                    material: materials.add(ColorMaterial::from(color)),
+                    transform: Transform::from_translation(translation.extend(z)),
+                    ..Default::default()
+                },
+                Shadow,
+                ShadowGeometry {
+                    layer,
+                    region: polygon,
+                },
+            ));
+        }
+    }
+}
+
+fn calculate_light_shadow(
+    light_position: Vec2,
+    obstacles: &[[Vec2; 4]],
+    world_boundary: (Vec2, Vec2),
+) -> MultiPolygon<f32> {
+    obstacles
+        .iter()
+        .map(|vertices| {
+            calculate_shadow_polygon_from_obstacle(light_position, vertices, world_boundary)
+        })
+        .fold(MultiPolygon::new(Vec::new()), |fold, polygon| {
+            fold.scaled_union(&MultiPolygon::new(vec![polygon]), 1e1)
+        })
+}
+
+fn calculate_shadow_layers(shadow_polygons: Vec<MultiPolygon<f32>>) -> ShadowLayers {
+    let union = shadow_polygons
+        .iter()
+        .fold(MultiPolygon::new(Vec::new()), |fold, polygon| {
+            fold.scaled_union(polygon, 1e1)
+        });
+    let intersection = shadow_polygons
+        .into_iter()
+        .reduce(|fold, polygon| fold.scaled_intersection(&polygon, 1e1));
+    ShadowLayers {
+        union,
+        intersection,
+    }
+}
+
+fn world_polygon((lower, upper): (Vec2, Vec2)) -> MultiPolygon<f32> {
+    MultiPolygon::new(vec![Polygon::new(
+        LineString::from(vec![
+            (lower.x, lower.y),
+            (upper.x, lower.y),
+            (upper.x, upper.y),
+            (lower.x, upper.y),
+        ]),
+        Vec::new(),
+    )])
+}
+
+fn calculate_vertices(transform: &Transform) -> [Vec2; 4] {
+    let rotation = Vec2::from_angle(transform.rotation.to_euler(EulerRot::YXZ).2);
+    let size = transform.scale;
+    let translation = transform.translation.truncate();
+    [
+        rotation.rotate(Vec2::new(-size.x / 2., -size.y / 2.)) + translation,
+        rotation.rotate(Vec2::new(size.x / 2., -size.y / 2.)) + translation,
+        rotation.rotate(Vec2::new(size.x / 2., size.y / 2.)) + translation,
+        rotation.rotate(Vec2::new(-size.x / 2., size.y / 2.)) + translation,
+    ]
+}
+
+fn calculate_intersection_to_world_bondary(
+    u: Vec2,
+    v: Vec2,
+    world_boundary: &(Vec2, Vec2),
+) -> Vec2 {
+    let ray = v - u;
+
+    // 横の衝突
+    let s = if ray.x < 0.0 {
+        (world_boundary.0.x - u.x) / ray.x
+    } else {
+        (world_boundary.1.x - u.x) / ray.x
+    };
+
+    // 縦の衝突
+    let t = if ray.y < 0.0 {
+        (world_boundary.0.y - u.y) / ray.y
+    } else {
+        (world_boundary.1.y - u.y) / ray.y
+    };
+    if s < t {
+        if ray.x < 0.0 {
+            Vec2::new(world_boundary.0.x, u.y + ray.y * s)
+        } else {
+            Vec2::new(world_boundary.1.x, u.y + ray.y * s)
+        }
+    } else {
+        if ray.y < 0.0 {
+            Vec2::new(u.x + ray.x * t, world_boundary.0.y)
+        } else {
+            Vec2::new(u.x + ray.x * t, world_boundary.1.y)
+        }
+    }
+}
+
+fn calculate_shadow_polygon_from_obstacle(
+    light_position: Vec2,
+    obstacle_vertices: &[Vec2; 4],
+    world_boundary: (Vec2, Vec2),
+) -> Polygon<f32> {
+    let world_vertices = [
+        world_boundary.1,
+        Vec2::new(world_boundary.0.x, world_boundary.1.y),
+        world_boundary.0,
+        Vec2::new(world_boundary.1.x, world_boundary.0.y),
+    ];
+
+    let obstacle_polygon = Polygon::<f32>::new(
+        LineString::from_iter(obstacle_vertices.iter().map(|v| v.to_array())),
+        Vec::new(),
+    );
+
+    let multi_points = MultiPoint::from_iter(
+        obstacle_vertices
+            .iter()
+            .map(|v| v.to_array())
+            // 壁との交点 (壁の外にはみ出した頂点の先は影にならない)
+            .chain(obstacle_vertices.iter().filter_map(|&v| {
+                let w = calculate_intersection_to_world_bondary(light_position, v, &world_boundary);
+                (light_position.distance_squared(w) >= light_position.distance_squared(v))
+                    .then_some(w.to_array())
+            }))
+            // 死角となっている四隅
+            .chain(
+                world_vertices
+                    .iter()
+                    .copied()
+                    .filter(|v| {
+                        obstacle_polygon
+                            .intersects(&Line::new(light_position.to_array(), v.to_array()))
+                    })
+                    .map(|v| v.to_array()),
+            ),
+    );
+
+    multi_points.convex_hull()
+}
+
+/// The cursor's offset from the centre of the window in pixels, y up.
+fn cursor_offset(window: &Window) -> Option<Vec2> {
+    let cursor = window.cursor_position()?;
+    Some(Vec2::new(
+        cursor.x - window.width() / 2.0,
+        window.height() / 2.0 - cursor.y,
+    ))
+}
+
+fn scale_world_with_scroll(
+    mut scroll_evr: EventReader<MouseWheel>,
+    actions: Res<Actions>,
+    mut zoom: ResMut<Zoom>,
+    cursor_position: Res<WorldCoords>,
+    q_window: Query<&Window, With<PrimaryWindow>>,
+    settings: Res<Settings>,
+) {
+    // キーでは画面の中心に向かって拡大縮小する
+    if actions.just_pressed(Action::ZoomIn) || actions.just_pressed(Action::ZoomOut) {
+        if actions.just_pressed(Action::ZoomIn) {
+            zoom.target *= 0.85;
+        }
+        if actions.just_pressed(Action::ZoomOut) {
+            zoom.target *= 1.15;
+        }
+        zoom.target = zoom.target.clamp(settings.zoom_min, settings.zoom_max);
+        zoom.anchor = None;
+    }
+    if scroll_evr.is_empty() {
+        return;
+    }
+    for ev in scroll_evr.iter() {
+        if ev.y > 0.0 {
+            zoom.target *= 0.85;
+        } else if ev.y < 0.0 {
+            zoom.target *= 1.15;
+        }
+    }
+    zoom.target = zoom.target.clamp(settings.zoom_min, settings.zoom_max);
+    zoom.anchor = q_window
+        .get_single()
+        .ok()
+        .and_then(cursor_offset)
+        .map(|offset| (cursor_position.0, offset));
+}
+
+fn ease_zoom(time: Res<Time>, mut zoom: ResMut<Zoom>, mut world_scale: ResMut<WorldScale>) {
+    if world_scale.0 == zoom.target {
+        return;
+    }
+    let t = 1.0 - (-ZOOM_EASING * time.delta_seconds()).exp();
+    world_scale.0 += (zoom.target - world_scale.0) * t;
+    if (world_scale.0 - zoom.target).abs() < 1e-3 * zoom.target {
+        world_scale.0 = zoom.target;
+        zoom.anchor = None;
+    }
+}
+
+/// Pans while the drag-pan button is held down.
+fn pan_with_drag(
+    actions: Res<Actions>,
+    mut pan: ResMut<DragPan>,
+    mut zoom: ResMut<Zoom>,
+    q_window: Query<&Window, With<PrimaryWindow>>,
+    mut query: Query<(&mut Transform, &OrthographicProjection), With<CameraLabel>>,
+) {
+    let Some(cursor) = q_window.get_single().ok().and_then(cursor_offset) else {
+        return;
+    };
+    if actions.just_pressed(Action::DragPan) {
+        pan.last = Some(cursor);
+    }
+    if !actions.pressed(Action::DragPan) {
+        pan.last = None;
+        return;
+    }
+    // パネルの上で押されたときは動かさない
+    let Some(last) = pan.last else {
+        return;
+    };
+    pan.last = Some(cursor);
+    let delta = cursor - last;
+    if delta == Vec2::ZERO {
+        return;
+    }
+    zoom.anchor = None;
+    let Ok((mut transform, projection)) = query.get_single_mut() else {
+        return;
+    };
+    transform.translation -= (delta * projection.scale).extend(0.0);
+}
+
+fn resize_floor(world_size: Res<WorldSize>, mut floors: Query<&mut Sprite, With<Floor>>) {
+    if world_size.is_changed() {
+        for mut sprite in floors.iter_mut() {
+            sprite.custom_size = Some(world_size.0);
+        }
+    }
+}
+
+fn zoom_reset(
+    actions: Res<Actions>,
+    mut world_scale: ResMut<WorldScale>,
+    mut zoom: ResMut<Zoom>,
+    mut query: Query<&mut Transform, With<CameraLabel>>,
+) {
+    if actions.just_pressed(Action::ResetZoom) {
+        if let Ok(mut transform) = query.get_single_mut() {
+            transform.translation.x = 0.0;
+            transform.translation.y = 0.0;
+        }
+        world_scale.0 = 1.0;
+        *zoom = Zoom::default();
+    }
+}
+
+fn change_camera_scale(
+    world_scale: Res<WorldScale>,
+    world_size: Res<WorldSize>,
+    zoom: Res<Zoom>,
+    q_window: Query<Ref<Window>, With<PrimaryWindow>>,
+    mut query: Query<(&mut OrthographicProjection, &mut Transform), With<CameraLabel>>,
+) {
+    let Ok(window) = q_window.get_single() else {
+        return;
+    };
+    if window.width() <= 0.0 || window.height() <= 0.0 {
+        return;
+    }
+    if world_scale.is_changed() || world_size.is_changed() || window.is_changed() {
+        // 倍率 1 で部屋全体が窓に収まる
+        let fit = (world_size.0 / Vec2::new(window.width(), window.height())).max_element();
+        let Ok((mut camera, mut transform)) = query.get_single_mut() else {
+            return;
+        };
+        camera.scale = world_scale.0 * fit;
+        // カーソルの下の点を動かさずに拡大縮小する
+        if let Some((point, offset)) = zoom.anchor {
+            transform.translation = (point - offset * camera.scale).extend(transform.translation.z);
+        }
+    }
+}
+
+fn screen_move(
+    actions: Res<Actions>,
+    time: Res<Time>,
+    world_size: Res<WorldSize>,
+    settings: Res<Settings>,
+    mut query: Query<&mut Transform, With<CameraLabel>>,
+) {
+    let speed = world_size.0.x * settings.pan_speed;
+
+    let Ok(mut camera) = query.get_single_mut() else {
+        return;
+    };
+    if actions.pressed(Action::PanRight) {
+        camera.translation.x += speed * time.delta_seconds();
+    }
+    if actions.pressed(Action::PanLeft) {
+        camera.translation.x -= speed * time.delta_seconds();
+    }
+    if actions.pressed(Action::PanUp) {
+        camera.translation.y += speed * time.delta_seconds();
+    }
+    if actions.pressed(Action::PanDown) {
+        camera.translation.y -= speed * time.delta_seconds();
+    }
+
+    let (lower, upper) = world_size.boundary();
+    camera.translation.x = camera.translation.x.clamp(lower.x, upper.x);
+    camera.translation.y = camera.translation.y.clamp(lower.y, upper.y);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn_scene(
+        mut commands: Commands,
+        mut meshes: ResMut<Assets<Mesh>>,
+        mut materials: ResMut<Assets<ColorMaterial>>,
+        settings: Res<Settings>,
+    ) {
+        spawn_light_at(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            &settings,
+            Vec2::new(-200.0, 0.0),
+            "Spot".to_string(),
+        );
+        commands.spawn((
+            TransformBundle::from_transform(
+                Transform::from_xyz(0.0, 0.0, OBSTACLE_Z).with_scale(Vec3::new(40.0, 40.0, 1.0)),
+            ),
+            Obstacle,
+        ));
+    }
+
+    #[test]
+    fn plugin_casts_shadows_without_a_window() {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            AssetPlugin::default(),
+            bevy::input::InputPlugin,
+        ))
+        .add_asset::<Mesh>()
+        .add_asset::<ColorMaterial>()
+        .add_asset::<Image>()
+        .add_asset::<Shader>()
+        .add_plugins(bevy::gizmos::GizmoPlugin)
+        .add_plugins(MuseumPlugin {
+            camera: false,
+            sample_scene: false,
+            ui: false,
+            ..default()
+        })
+        .add_systems(Startup, spawn_scene);
+
+        // 影は別スレッドで計算するので、フレームごとに出来上がりを待つ
+        for _ in 0..10 {
+            app.update();
+            let mut jobs = app.world.resource_mut::<ShadowJobs>();
+            for (task, _) in jobs.lights.iter_mut().flatten() {
+                task.wait();
+            }
+            if let Some(layers) = &mut jobs.layers {
+                layers.wait();
+            }
+            let idle = !jobs.outdated && jobs.lights.is_none() && jobs.layers.is_none();
+            let mut shadows = app.world.query_filtered::<(), With<Shadow>>();
+            if idle && shadows.iter(&app.world).next().is_some() {
+                return;
+            }
+        }
+        panic!("no shadow was spawned");
+    }
+}
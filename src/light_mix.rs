@@ -0,0 +1,100 @@
+use bevy::ecs as bevy_ecs;
+use bevy::prelude::*;
+use geo::MultiPolygon;
+
+use crate::geo_scaled::ScaledBooleanOps;
+
+/// The colour of the gel in front of a light, and how bright it shines
+/// through it in the light mix. The photometric intensity is the
+/// `Luminaire`'s.
+#[derive(Component, Clone, Copy)]
+pub struct LightColor {
+    pub color: Color,
+    pub gain: f32,
+}
+
+impl Default for LightColor {
+    fn default() -> Self {
+        Self {
+            color: Color::WHITE,
+            gain: 1.0,
+        }
+    }
+}
+
+impl LightColor {
+    /// Linear RGB added to every region the light reaches.
+    pub fn contribution(&self) -> Vec3 {
+        let [r, g, b, _] = self.color.as_linear_rgba_f32();
+        Vec3::new(r, g, b) * self.gain
+    }
+}
+
+/// Splits `room` into the regions of the arrangement of the lit areas, and
+/// colours every region with the sum of the lights that reach it.
+pub fn calculate_light_mix(
+    room: MultiPolygon<f32>,
+    lit_areas: impl IntoIterator<Item = (MultiPolygon<f32>, Vec3)>,
+) -> Vec<(MultiPolygon<f32>, Color)> {
+    let mut regions = vec![(room, Vec3::ZERO)];
+    for (lit, contribution) in lit_areas {
+        regions = regions
+            .into_iter()
+            .flat_map(|(region, sum)| {
+                [
+                    (region.scaled_intersection(&lit, 1e1), sum + contribution),
+                    (region.scaled_difference(&lit, 1e1), sum),
+                ]
+            })
+            .filter(|(region, _)| !region.0.is_empty())
+            .collect();
+    }
+    regions
+        .into_iter()
+        .map(|(region, sum)| {
+            let sum = sum.min(Vec3::ONE);
+            (region, Color::rgb_linear(sum.x, sum.y, sum.z))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::{polygon, Area};
+
+    fn square(x: f32, y: f32, size: f32) -> MultiPolygon<f32> {
+        MultiPolygon::new(vec![polygon![
+            (x: x, y: y),
+            (x: x + size, y: y),
+            (x: x + size, y: y + size),
+            (x: x, y: y + size),
+        ]])
+    }
+
+    #[test]
+    fn overlapping_lights_add_up() {
+        let red = Vec3::new(1.0, 0.0, 0.0);
+        let blue = Vec3::new(0.0, 0.0, 1.0);
+        let regions = calculate_light_mix(
+            square(0.0, 0.0, 30.0),
+            [
+                (square(0.0, 0.0, 20.0), red),
+                (square(10.0, 10.0, 20.0), blue),
+            ],
+        );
+
+        let area_of = |color: Color| -> f32 {
+            regions
+                .iter()
+                .filter(|(_, c)| *c == color)
+                .map(|(region, _)| region.unsigned_area())
+                .sum()
+        };
+        assert_eq!(regions.len(), 4);
+        assert_eq!(area_of(Color::rgb_linear(1.0, 0.0, 1.0)), 100.0);
+        assert_eq!(area_of(Color::rgb_linear(1.0, 0.0, 0.0)), 300.0);
+        assert_eq!(area_of(Color::rgb_linear(0.0, 0.0, 1.0)), 300.0);
+        assert_eq!(area_of(Color::rgb_linear(0.0, 0.0, 0.0)), 200.0);
+    }
+}
@@ -0,0 +1,281 @@
+use bevy::ecs as bevy_ecs;
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_egui::{egui, EguiContexts};
+
+use crate::actions::{Action, Actions};
+use crate::{CameraLabel, Light, Obstacle, SceneScale, WorldCoords, COLOR_DIMENSION, COLOR_RULER};
+
+// 画面上でこの距離 (px) 以内なら頂点や光源に吸着する
+const SNAP_DISTANCE: f32 = 10.0;
+const TICK_LENGTH: f32 = 6.0;
+const LABEL_OFFSET: egui::Vec2 = egui::vec2(8.0, -24.0);
+
+/// The corners of an obstacle in its own coordinates.
+const CORNERS: [Vec2; 4] = [
+    Vec2::new(-0.5, -0.5),
+    Vec2::new(0.5, -0.5),
+    Vec2::new(0.5, 0.5),
+    Vec2::new(-0.5, 0.5),
+];
+
+/// One end of a measurement.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Anchor {
+    Fixed(Vec2),
+    /// A point on an entity, in its own coordinates, so it follows the entity.
+    Attached(Entity, Vec2),
+}
+
+impl Anchor {
+    /// Where the anchor is now, or `None` if its entity is gone.
+    pub fn resolve(&self, transform_of: impl Fn(Entity) -> Option<Transform>) -> Option<Vec2> {
+        match *self {
+            Anchor::Fixed(point) => Some(point),
+            Anchor::Attached(entity, local) => transform_of(entity)
+                .map(|transform| transform.transform_point(local.extend(0.0)).truncate()),
+        }
+    }
+}
+
+/// The two-click distance and angle tool.
+#[derive(Resource, Default)]
+pub struct Ruler {
+    pub active: bool,
+    start: Option<Anchor>,
+    end: Option<Anchor>,
+}
+
+/// A measurement kept in the scene. It follows the entities its ends are
+/// attached to and disappears with them.
+#[derive(Component, Clone, Copy)]
+pub struct Dimension {
+    pub from: Anchor,
+    pub to: Anchor,
+}
+
+/// The candidate closest to `point` within `radius`, or `point` itself.
+pub fn snap(
+    point: Vec2,
+    radius: f32,
+    candidates: impl IntoIterator<Item = (Anchor, Vec2)>,
+) -> (Anchor, Vec2) {
+    candidates
+        .into_iter()
+        .map(|(anchor, position)| (position.distance(point), anchor, position))
+        .filter(|(distance, _, _)| *distance <= radius)
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map_or((Anchor::Fixed(point), point), |(_, anchor, position)| {
+            (anchor, position)
+        })
+}
+
+/// The distance in metres and the angle from the x axis in degrees.
+pub fn describe(from: Vec2, to: Vec2, metres_per_unit: f32) -> String {
+    let ray = to - from;
+    let degrees = ray.y.atan2(ray.x).to_degrees();
+    format!("{:.2} m, {:.0}°", ray.length() * metres_per_unit, degrees)
+}
+
+type SnapTargets<'w, 's> = (
+    Query<'w, 's, (Entity, &'static Transform), With<Light>>,
+    Query<'w, 's, (Entity, &'static Transform), With<Obstacle>>,
+);
+
+fn snap_to_scene(point: Vec2, radius: f32, (lights, obstacles): &SnapTargets) -> (Anchor, Vec2) {
+    let lights = lights.iter().map(|(entity, transform)| {
+        (
+            Anchor::Attached(entity, Vec2::ZERO),
+            transform.translation.truncate(),
+        )
+    });
+    let corners = obstacles.iter().flat_map(|(entity, transform)| {
+        CORNERS.map(|corner| {
+            (
+                Anchor::Attached(entity, corner),
+                transform.transform_point(corner.extend(0.0)).truncate(),
+            )
+        })
+    });
+    snap(point, radius, lights.chain(corners))
+}
+
+fn snap_radius(cameras: &Query<&OrthographicProjection, With<CameraLabel>>) -> f32 {
+    SNAP_DISTANCE
+        * cameras
+            .get_single()
+            .map_or(1.0, |projection| projection.scale)
+}
+
+pub fn toggle_ruler(actions: Res<Actions>, mut ruler: ResMut<Ruler>) {
+    if actions.just_pressed(Action::Ruler) {
+        *ruler = Ruler {
+            active: !ruler.active,
+            ..default()
+        };
+    }
+}
+
+/// Places the ruler's ends. The click isn't passed on, so it doesn't also
+/// select or drag what it snapped to.
+pub fn use_ruler(
+    mut actions: ResMut<Actions>,
+    mut ruler: ResMut<Ruler>,
+    cursor_position: Res<WorldCoords>,
+    cameras: Query<&OrthographicProjection, With<CameraLabel>>,
+    targets: SnapTargets,
+) {
+    if !ruler.active || !actions.just_pressed(Action::Select) {
+        return;
+    }
+    actions.consume(Action::Select);
+    let (anchor, _) = snap_to_scene(cursor_position.0, snap_radius(&cameras), &targets);
+    if ruler.start.is_none() || ruler.end.is_some() {
+        ruler.start = Some(anchor);
+        ruler.end = None;
+    } else {
+        ruler.end = Some(anchor);
+    }
+}
+
+/// Keeps the finished measurement as a dimension.
+pub fn pin_dimension(mut commands: Commands, actions: Res<Actions>, mut ruler: ResMut<Ruler>) {
+    if !actions.just_pressed(Action::PinDimension) {
+        return;
+    }
+    if let (Some(from), Some(to)) = (ruler.start, ruler.end) {
+        commands.spawn((Dimension { from, to }, Name::new("Dimension")));
+        ruler.start = None;
+        ruler.end = None;
+    }
+}
+
+fn label(ctx: &egui::Context, id: impl std::hash::Hash, at: Option<Vec2>, text: String) {
+    let Some(at) = at else {
+        return;
+    };
+    egui::Area::new(egui::Id::new(id))
+        .fixed_pos(egui::pos2(at.x, at.y) + LABEL_OFFSET)
+        .interactable(false)
+        .show(ctx, |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| ui.label(text));
+        });
+}
+
+/// Draws the ruler and the dimensions with their lengths.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_measurements(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut gizmos: Gizmos,
+    ruler: Res<Ruler>,
+    dimensions: Query<(Entity, &Dimension)>,
+    transforms: Query<&Transform>,
+    cursor_position: Res<WorldCoords>,
+    q_window: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform, &OrthographicProjection), With<CameraLabel>>,
+    targets: SnapTargets,
+    scene_scale: Res<SceneScale>,
+) {
+    let Ok((camera, camera_transform, projection)) = cameras.get_single() else {
+        return;
+    };
+    let to_screen = |point: Vec2| camera.world_to_viewport(camera_transform, point.extend(0.0));
+    let transform_of = |entity| transforms.get(entity).ok().copied();
+    let ctx = contexts.ctx_mut();
+
+    for (entity, dimension) in dimensions.iter() {
+        let (Some(from), Some(to)) = (
+            dimension.from.resolve(transform_of),
+            dimension.to.resolve(transform_of),
+        ) else {
+            commands.entity(entity).despawn();
+            continue;
+        };
+        gizmos.line_2d(from, to, COLOR_DIMENSION);
+        let tick = (to - from).perp().normalize_or_zero() * TICK_LENGTH * projection.scale;
+        for end in [from, to] {
+            gizmos.line_2d(end - tick, end + tick, COLOR_DIMENSION);
+        }
+        label(
+            ctx,
+            ("dimension", entity),
+            to_screen((from + to) / 2.0),
+            describe(from, to, scene_scale.0),
+        );
+    }
+
+    if !ruler.active {
+        return;
+    }
+    // カーソルの吸着先を示す
+    let hovered = q_window
+        .get_single()
+        .ok()
+        .and_then(Window::cursor_position)
+        .map(|_| {
+            snap_to_scene(
+                cursor_position.0,
+                SNAP_DISTANCE * projection.scale,
+                &targets,
+            )
+        });
+    if let Some((Anchor::Attached(..), point)) = hovered {
+        gizmos.circle_2d(point, SNAP_DISTANCE * projection.scale, COLOR_RULER);
+    }
+    let Some(start) = ruler.start.and_then(|anchor| anchor.resolve(transform_of)) else {
+        return;
+    };
+    let end = match ruler.end {
+        Some(anchor) => anchor.resolve(transform_of),
+        None => hovered.map(|(_, point)| point),
+    };
+    gizmos.circle_2d(start, 3.0 * projection.scale, COLOR_RULER);
+    if let Some(end) = end {
+        gizmos.line_2d(start, end, COLOR_RULER);
+        label(
+            ctx,
+            "ruler",
+            to_screen((start + end) / 2.0),
+            describe(start, end, scene_scale.0),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snap_picks_the_closest_target_in_reach() {
+        let light = Entity::from_raw(1);
+        let obstacle = Entity::from_raw(2);
+        let candidates = [
+            (Anchor::Attached(light, Vec2::ZERO), Vec2::new(10.0, 0.0)),
+            (
+                Anchor::Attached(obstacle, Vec2::new(0.5, 0.5)),
+                Vec2::new(4.0, 3.0),
+            ),
+        ];
+        assert_eq!(
+            snap(Vec2::ZERO, 6.0, candidates),
+            (
+                Anchor::Attached(obstacle, Vec2::new(0.5, 0.5)),
+                Vec2::new(4.0, 3.0)
+            )
+        );
+        let far = Vec2::new(-50.0, 0.0);
+        assert_eq!(snap(far, 6.0, candidates), (Anchor::Fixed(far), far));
+    }
+
+    #[test]
+    fn describe_uses_metres_and_degrees() {
+        assert_eq!(
+            describe(Vec2::ZERO, Vec2::new(0.0, 150.0), 0.02),
+            "3.00 m, 90°"
+        );
+        assert_eq!(
+            describe(Vec2::new(10.0, 10.0), Vec2::new(-40.0, 10.0), 0.1),
+            "5.00 m, 180°"
+        );
+    }
+}
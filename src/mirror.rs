@@ -0,0 +1,284 @@
+use bevy::ecs as bevy_ecs;
+use bevy::prelude::*;
+use geo::{Intersects, Line, LineString, MultiPolygon, Polygon};
+
+use crate::actions::{Action, Actions};
+use crate::geo_scaled::ScaledBooleanOps;
+use crate::{
+    calculate_light_shadow, calculate_vertices, Obstacle, WorldCoords, COLOR_MIRROR, MIRROR_Z,
+};
+
+// 鏡の辺を選べるカーソルからの距離
+const PICK_DISTANCE: f32 = 10.0;
+
+/// Which edges of an obstacle reflect light. Edge `i` runs from vertex `i` to
+/// vertex `i + 1` of [`calculate_vertices`].
+#[derive(Component, Clone, Copy, Default)]
+pub struct Mirror(pub [bool; 4]);
+
+impl Mirror {
+    pub fn edges(&self, vertices: &[Vec2; 4]) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+        let vertices = *vertices;
+        (0..4)
+            .filter(|&i| self.0[i])
+            .map(move |i| (vertices[i], vertices[(i + 1) % 4]))
+    }
+}
+
+/// How many times light is reflected off mirrors.
+#[derive(Resource, Clone, Copy)]
+pub struct BounceDepth(pub usize);
+
+impl Default for BounceDepth {
+    fn default() -> Self {
+        Self(1)
+    }
+}
+
+#[derive(Component)]
+pub struct MirrorMark;
+
+/// A light seen in a mirror, with everything in mirror coordinates: the
+/// mirrors the light passes through, the last one first, and what blocks it.
+struct VirtualLight {
+    position: Vec2,
+    windows: Vec<(Vec2, Vec2)>,
+    occluders: Vec<[Vec2; 4]>,
+    lit: Option<MultiPolygon<f32>>,
+}
+
+// 鏡の外側 (障害物の頂点は反時計回りなので右側) にあるか
+fn is_in_front(mirror: (Vec2, Vec2), point: Vec2) -> bool {
+    (mirror.1 - mirror.0).perp_dot(point - mirror.0) < 0.0
+}
+
+fn reflect(mirror: (Vec2, Vec2), point: Vec2) -> Vec2 {
+    let direction = (mirror.1 - mirror.0).normalize();
+    let foot = mirror.0 + direction * (point - mirror.0).dot(direction);
+    2.0 * foot - point
+}
+
+fn to_polygon(points: &[Vec2]) -> MultiPolygon<f32> {
+    MultiPolygon::new(vec![Polygon::new(
+        LineString::from_iter(points.iter().map(|p| p.to_array())),
+        Vec::new(),
+    )])
+}
+
+/// The area lit by light from `light_position` after one to `depth`
+/// reflections off `mirrors`, inside `world_boundary`.
+///
+/// Each reflection is a virtual light mirrored across the edge, lighting only
+/// the wedge beyond the edge. Obstacles on the way to the mirror are mirrored
+/// with it, so they shade the reflection as well.
+pub fn calculate_reflections(
+    light_position: Vec2,
+    obstacles: &[[Vec2; 4]],
+    mirrors: &[(Vec2, Vec2)],
+    depth: usize,
+    world_boundary: (Vec2, Vec2),
+) -> MultiPolygon<f32> {
+    let (lower, upper) = world_boundary;
+    let room = to_polygon(&[
+        lower,
+        Vec2::new(upper.x, lower.y),
+        upper,
+        Vec2::new(lower.x, upper.y),
+    ]);
+    let mut lit = MultiPolygon::new(Vec::new());
+    let mut lights = vec![VirtualLight {
+        position: light_position,
+        windows: Vec::new(),
+        occluders: obstacles.to_vec(),
+        lit: None,
+    }];
+    for _ in 0..depth {
+        let mut reflections = Vec::new();
+        for light in &lights {
+            for &mirror in mirrors {
+                if mirror.0.distance_squared(mirror.1) < 1e-6
+                    || !is_in_front(mirror, light.position)
+                {
+                    continue;
+                }
+                let line = Line::new(mirror.0.to_array(), mirror.1.to_array());
+                if light.lit.as_ref().is_some_and(|lit| !lit.intersects(&line)) {
+                    continue;
+                }
+                let in_front =
+                    |vertices: &&[Vec2; 4]| vertices.iter().any(|&v| is_in_front(mirror, v));
+                let occluders: Vec<[Vec2; 4]> = light
+                    .occluders
+                    .iter()
+                    .filter(in_front)
+                    .map(|vertices| vertices.map(|v| reflect(mirror, v)))
+                    .chain(obstacles.iter().filter(in_front).copied())
+                    .collect();
+                let windows: Vec<(Vec2, Vec2)> = std::iter::once(mirror)
+                    .chain(
+                        light
+                            .windows
+                            .iter()
+                            .map(|&(a, b)| (reflect(mirror, a), reflect(mirror, b))),
+                    )
+                    .collect();
+                let position = reflect(mirror, light.position);
+
+                // 仮想光源が部屋の外に出ても影が作れるように広げる
+                let boundary = (lower.min(position) - 1.0, upper.max(position) + 1.0);
+                let far = boundary.0.distance(boundary.1) * 2.0;
+                let ray = |v: Vec2| position + (v - position).normalize() * far;
+                let (a, b) = mirror;
+                let wedge = to_polygon(&[a, b, ray(b), ray(a)]);
+                let mut area = room.scaled_intersection(&wedge, 1e1);
+                for &(a, b) in &windows[1..] {
+                    let cone = to_polygon(&[position, ray(a), ray(b)]);
+                    area = area.scaled_intersection(&cone, 1e1);
+                }
+                let area = area.scaled_difference(
+                    &calculate_light_shadow(position, &occluders, boundary),
+                    1e1,
+                );
+                if area.0.is_empty() {
+                    continue;
+                }
+                lit = lit.scaled_union(&area, 1e1);
+                reflections.push(VirtualLight {
+                    position,
+                    windows,
+                    occluders,
+                    lit: Some(area),
+                });
+            }
+        }
+        lights = reflections;
+    }
+    lit
+}
+
+pub fn toggle_mirror_edge(
+    mut commands: Commands,
+    mut obstacles: Query<(Entity, &Transform, Option<&mut Mirror>), With<Obstacle>>,
+    actions: Res<Actions>,
+    cursor_position: Res<WorldCoords>,
+) {
+    if !actions.just_pressed(Action::ToggleMirror) {
+        return;
+    }
+    let cursor = cursor_position.0;
+    let distance = |(a, b): (Vec2, Vec2)| {
+        let t = ((cursor - a).dot(b - a) / a.distance_squared(b)).clamp(0.0, 1.0);
+        cursor.distance(a.lerp(b, t))
+    };
+    let nearest = obstacles
+        .iter()
+        .flat_map(|(entity, transform, _)| {
+            let vertices = calculate_vertices(transform);
+            (0..4).map(move |i| (entity, i, distance((vertices[i], vertices[(i + 1) % 4]))))
+        })
+        .filter(|&(_, _, d)| d <= PICK_DISTANCE)
+        .min_by(|a, b| a.2.total_cmp(&b.2));
+    let Some((entity, edge, _)) = nearest else {
+        return;
+    };
+    match obstacles.get_mut(entity) {
+        Ok((_, _, Some(mut mirror))) => mirror.0[edge] = !mirror.0[edge],
+        _ => {
+            let mut mirror = Mirror::default();
+            mirror.0[edge] = true;
+            commands.entity(entity).insert(mirror);
+        }
+    }
+}
+
+pub fn cycle_bounce_depth(actions: Res<Actions>, mut depth: ResMut<BounceDepth>) {
+    if actions.just_pressed(Action::CycleBounceDepth) {
+        depth.0 = (depth.0 + 1) % 4;
+    }
+}
+
+pub fn draw_mirror_edges(
+    mut commands: Commands,
+    mirrors: Query<(Ref<Transform>, Ref<Mirror>)>,
+    marks: Query<Entity, With<MirrorMark>>,
+    mut removed: RemovedComponents<Mirror>,
+) {
+    let removed = removed.iter().count() > 0;
+    if !removed
+        && !mirrors
+            .iter()
+            .any(|(transform, mirror)| transform.is_changed() || mirror.is_changed())
+    {
+        return;
+    }
+    for entity in marks.iter() {
+        commands.entity(entity).despawn();
+    }
+    for (transform, mirror) in mirrors.iter() {
+        for (a, b) in mirror.edges(&calculate_vertices(&transform)) {
+            commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: COLOR_MIRROR,
+                        custom_size: Some(Vec2::new(a.distance(b), 4.0)),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(((a + b) / 2.0).extend(MIRROR_Z))
+                        .with_rotation(Quat::from_rotation_z(Vec2::X.angle_between(b - a))),
+                    ..default()
+                },
+                MirrorMark,
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::Point;
+
+    const WORLD_BOUNDARY: (Vec2, Vec2) = (Vec2::new(-480.0, -360.0), Vec2::new(480.0, 360.0));
+
+    fn square(center: Vec2, half: Vec2) -> [Vec2; 4] {
+        [
+            center - half,
+            center + Vec2::new(half.x, -half.y),
+            center + half,
+            center + Vec2::new(-half.x, half.y),
+        ]
+    }
+
+    #[test]
+    fn mirror_lights_its_wedge_unless_the_way_is_blocked() {
+        let wall = square(Vec2::new(110.0, 0.0), Vec2::new(10.0, 100.0));
+        // 左の辺が鏡
+        let mirrors: Vec<_> = Mirror([false, false, false, true]).edges(&wall).collect();
+        let blocker = square(Vec2::new(50.0, 25.0), Vec2::new(5.0, 5.0));
+        let lit = calculate_reflections(
+            Vec2::new(0.0, 50.0),
+            &[wall, blocker],
+            &mirrors,
+            1,
+            WORLD_BOUNDARY,
+        );
+
+        let at = |x: f32, y: f32| Point::new(x, y);
+        assert!(lit.intersects(&at(0.0, 50.0)));
+        assert!(lit.intersects(&at(0.0, -150.0)));
+        // 鏡の範囲外、鏡の裏、鏡への途中で遮られる点
+        assert!(!lit.intersects(&at(50.0, -300.0)));
+        assert!(!lit.intersects(&at(300.0, 0.0)));
+        assert!(!lit.intersects(&at(0.0, -50.0)));
+
+        assert!(calculate_reflections(
+            Vec2::new(0.0, 50.0),
+            &[wall, blocker],
+            &mirrors,
+            0,
+            WORLD_BOUNDARY
+        )
+        .0
+        .is_empty());
+    }
+}
@@ -0,0 +1,420 @@
+use bevy::input::mouse::MouseWheel;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::actions::{Action, Binding, ShowHelp};
+use crate::animation::Spin;
+use crate::beam::Beam;
+use crate::exchange::ExchangeEditor;
+use crate::floor_plan::PlanEditor;
+use crate::illuminance::Luminaire;
+use crate::light_mix::LightColor;
+use crate::measure::{describe, Dimension};
+use crate::settings::{Settings, ShowSettings};
+use crate::soft_shadow::Emitter;
+use crate::solo::{toggle_muted, Muted, Solo};
+use crate::{select, unselect, Light, Obstacle, SceneScale, Selected, Theta, WorldSize};
+
+// 変更があったときだけ書き戻して、毎フレーム影を計算し直さない
+fn edit_vec2(ui: &mut egui::Ui, label: &str, value: &mut Vec2, min: f32) -> bool {
+    ui.label(label);
+    let changed = ui.horizontal(|ui| {
+        ui.add(egui::DragValue::new(&mut value.x).clamp_range(min..=f32::MAX))
+            .changed()
+            | ui.add(egui::DragValue::new(&mut value.y).clamp_range(min..=f32::MAX))
+                .changed()
+    });
+    ui.end_row();
+    changed.inner
+}
+
+fn edit_angle(ui: &mut egui::Ui, label: &str, radians: &mut f32) -> bool {
+    ui.label(label);
+    let changed = ui.drag_angle(radians).changed();
+    ui.end_row();
+    changed
+}
+
+fn rotation(transform: &Transform) -> f32 {
+    transform.rotation.to_euler(EulerRot::YXZ).2
+}
+
+#[allow(clippy::too_many_arguments)]
+fn edit_light(
+    ui: &mut egui::Ui,
+    commands: &mut Commands,
+    entity: Entity,
+    transform: &mut Mut<Transform>,
+    color: &mut Mut<LightColor>,
+    emitter: &mut Mut<Emitter>,
+    luminaire: Option<Mut<Luminaire>>,
+    theta: Option<Mut<Theta>>,
+    spin: Option<Mut<Spin>>,
+    beam: Option<Mut<Beam>>,
+) {
+    egui::Grid::new(entity).num_columns(2).show(ui, |ui| {
+        let mut position = transform.translation.truncate();
+        if edit_vec2(ui, "Position", &mut position, f32::MIN) {
+            transform.translation = position.extend(transform.translation.z);
+        }
+
+        ui.label("Size");
+        let mut radius = transform.scale.x;
+        if ui
+            .add(egui::DragValue::new(&mut radius).clamp_range(1.0..=100.0))
+            .changed()
+        {
+            transform.scale = Vec3::new(radius, radius, 1.0);
+            if let Emitter::Disc(_) = **emitter {
+                **emitter = Emitter::Disc(radius);
+            }
+        }
+        ui.end_row();
+
+        ui.label("Colour");
+        ui.horizontal(|ui| {
+            let [r, g, b, _] = color.color.as_rgba_f32();
+            let mut rgb = [r, g, b];
+            if ui.color_edit_button_rgb(&mut rgb).changed() {
+                color.color = Color::rgb(rgb[0], rgb[1], rgb[2]);
+            }
+            let mut gain = color.gain;
+            if ui
+                .add(
+                    egui::DragValue::new(&mut gain)
+                        .speed(0.05)
+                        .clamp_range(0.0..=10.0)
+                        .prefix("× "),
+                )
+                .changed()
+            {
+                color.gain = gain;
+            }
+        });
+        ui.end_row();
+
+        if let Some(mut luminaire) = luminaire {
+            ui.label("Intensity");
+            let mut candela = luminaire.intensity;
+            if ui
+                .add(
+                    egui::DragValue::new(&mut candela)
+                        .speed(10.0)
+                        .clamp_range(0.0..=f32::MAX)
+                        .suffix(" cd"),
+                )
+                .changed()
+            {
+                luminaire.intensity = candela;
+            }
+            ui.end_row();
+            ui.label("Height");
+            let mut height = luminaire.height;
+            if ui
+                .add(
+                    egui::DragValue::new(&mut height)
+                        .speed(0.05)
+                        .clamp_range(0.1..=f32::MAX)
+                        .suffix(" m"),
+                )
+                .changed()
+            {
+                luminaire.height = height;
+            }
+            ui.end_row();
+        }
+
+        let mut direction = theta.as_ref().map_or(0.0, |theta| theta.0);
+        if edit_angle(ui, "Direction", &mut direction) {
+            match theta {
+                Some(mut theta) => theta.0 = direction,
+                None => {
+                    commands.entity(entity).insert(Theta(direction, 0.0));
+                }
+            }
+        }
+        let mut turn = spin.as_ref().map_or(0.0, |spin| spin.0);
+        if edit_angle(ui, "Turn per second", &mut turn) {
+            match spin {
+                Some(mut spin) => spin.0 = turn,
+                None => {
+                    commands.entity(entity).insert(Spin(turn));
+                }
+            }
+        }
+
+        ui.label("Beam");
+        let mut limited = beam.is_some();
+        if ui.checkbox(&mut limited, "").changed() {
+            if limited {
+                commands.entity(entity).insert(Beam::default());
+            } else {
+                commands.entity(entity).remove::<Beam>();
+            }
+        }
+        ui.end_row();
+        if let Some(mut beam) = beam {
+            let mut width = beam.width;
+            if edit_angle(ui, "Beam width", &mut width) {
+                beam.width = width.clamp(0.0, std::f32::consts::TAU);
+            }
+            ui.label("Range");
+            let mut range = beam.range;
+            if ui
+                .add(egui::DragValue::new(&mut range).clamp_range(1.0..=f32::MAX))
+                .changed()
+            {
+                beam.range = range;
+            }
+            ui.end_row();
+        }
+    });
+}
+
+fn edit_obstacle(ui: &mut egui::Ui, entity: Entity, transform: &mut Mut<Transform>) {
+    egui::Grid::new(entity).num_columns(2).show(ui, |ui| {
+        let mut position = transform.translation.truncate();
+        if edit_vec2(ui, "Position", &mut position, f32::MIN) {
+            transform.translation = position.extend(transform.translation.z);
+        }
+        let mut angle = rotation(transform);
+        if edit_angle(ui, "Rotation", &mut angle) {
+            transform.rotation = Quat::from_rotation_z(angle);
+        }
+        let mut size = transform.scale.truncate();
+        if edit_vec2(ui, "Size", &mut size, 1.0) {
+            transform.scale = size.extend(1.0);
+        }
+    });
+}
+
+/// Lists the lights and obstacles, selects the one whose row is clicked and
+/// edits the selected one.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn scene_panel(
+    mut contexts: EguiContexts,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut lights: Query<
+        (
+            Entity,
+            Option<&Name>,
+            &mut Transform,
+            &mut LightColor,
+            &mut Emitter,
+            Option<&mut Luminaire>,
+            Option<&mut Theta>,
+            Option<&mut Spin>,
+            Option<&mut Beam>,
+            Option<&Muted>,
+            Option<Ref<Selected>>,
+        ),
+        With<Light>,
+    >,
+    mut obstacles: Query<
+        (Entity, Option<&Name>, &mut Transform, Option<Ref<Selected>>),
+        (With<Obstacle>, Without<Light>),
+    >,
+    selected: Query<Entity, With<Selected>>,
+    mut solo: ResMut<Solo>,
+    dimensions: Query<(Entity, &Dimension)>,
+    mut world_size: ResMut<WorldSize>,
+    mut scene_scale: ResMut<SceneScale>,
+    settings: Res<Settings>,
+    mut show_settings: ResMut<ShowSettings>,
+    mut show_help: ResMut<ShowHelp>,
+    mut plan_editor: ResMut<PlanEditor>,
+    mut exchange_editor: ResMut<ExchangeEditor>,
+) {
+    let mut clicked = None;
+    egui::SidePanel::left("scene_panel").show(contexts.ctx_mut(), |ui| {
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Settings").clicked() {
+                    show_settings.0 = !show_settings.0;
+                }
+                if ui.button("Controls").clicked() {
+                    show_help.0 = !show_help.0;
+                }
+                if ui.button("Floor plan").clicked() {
+                    plan_editor.open = !plan_editor.open;
+                }
+                if ui.button("Files").clicked() {
+                    exchange_editor.open = !exchange_editor.open;
+                }
+            });
+
+            ui.heading("Room");
+            egui::Grid::new("room").num_columns(2).show(ui, |ui| {
+                let mut size = world_size.0;
+                if edit_vec2(ui, "Size", &mut size, 10.0) {
+                    world_size.0 = size;
+                }
+                ui.label("Metres per unit");
+                let mut metres_per_unit = scene_scale.0;
+                if ui
+                    .add(
+                        egui::DragValue::new(&mut metres_per_unit)
+                            .speed(0.001)
+                            .clamp_range(0.0001..=100.0),
+                    )
+                    .changed()
+                {
+                    scene_scale.0 = metres_per_unit;
+                }
+                ui.end_row();
+                let metres = size * scene_scale.0;
+                ui.label("In metres");
+                ui.label(format!("{:.1} × {:.1}", metres.x, metres.y));
+                ui.end_row();
+            });
+
+            ui.separator();
+            ui.heading("Lights");
+            for (
+                entity,
+                name,
+                mut transform,
+                mut color,
+                mut emitter,
+                luminaire,
+                theta,
+                spin,
+                beam,
+                muted,
+                selection,
+            ) in lights.iter_mut()
+            {
+                let name =
+                    name.map_or_else(|| format!("Light {}", entity.index()), |n| n.to_string());
+                ui.horizontal(|ui| {
+                    let row = ui.selectable_label(selection.is_some(), name);
+                    if row.clicked() {
+                        clicked = Some(entity);
+                    }
+                    if selection.as_ref().is_some_and(|s| s.is_added()) {
+                        row.scroll_to_me(None);
+                    }
+                    if ui
+                        .selectable_label(solo.0 == Some(entity), "Solo")
+                        .clicked()
+                    {
+                        solo.toggle(entity);
+                    }
+                    if ui.selectable_label(muted.is_some(), "Mute").clicked() {
+                        toggle_muted(&mut commands, entity, muted.is_some());
+                    }
+                });
+                if selection.is_some() {
+                    ui.indent(entity, |ui| {
+                        edit_light(
+                            ui,
+                            &mut commands,
+                            entity,
+                            &mut transform,
+                            &mut color,
+                            &mut emitter,
+                            luminaire,
+                            theta,
+                            spin,
+                            beam,
+                        );
+                    });
+                }
+            }
+
+            ui.separator();
+            ui.heading("Obstacles");
+            for (entity, name, mut transform, selection) in obstacles.iter_mut() {
+                let name =
+                    name.map_or_else(|| format!("Obstacle {}", entity.index()), |n| n.to_string());
+                let row = ui.selectable_label(selection.is_some(), name);
+                if row.clicked() {
+                    clicked = Some(entity);
+                }
+                if selection.as_ref().is_some_and(|s| s.is_added()) {
+                    row.scroll_to_me(None);
+                }
+                if selection.is_some() {
+                    ui.indent(entity, |ui| edit_obstacle(ui, entity, &mut transform));
+                }
+            }
+
+            ui.separator();
+            ui.heading("Dimensions");
+            let transform_of = |entity| {
+                lights
+                    .get(entity)
+                    .map(|light| *light.2)
+                    .or_else(|_| obstacles.get(entity).map(|obstacle| *obstacle.2))
+                    .ok()
+            };
+            for (entity, dimension) in dimensions.iter() {
+                let (Some(from), Some(to)) = (
+                    dimension.from.resolve(transform_of),
+                    dimension.to.resolve(transform_of),
+                ) else {
+                    continue;
+                };
+                ui.horizontal(|ui| {
+                    ui.label(describe(from, to, scene_scale.0));
+                    if ui.button("Remove").clicked() {
+                        commands.entity(entity).despawn();
+                    }
+                });
+            }
+        });
+    });
+
+    // もう一度クリックすると選択を外す
+    if let Some(entity) = clicked {
+        let was_selected = selected.contains(entity);
+        for e in selected.iter() {
+            unselect(&mut commands, e);
+        }
+        if !was_selected {
+            let outline = if obstacles.contains(entity) {
+                shape::Quad::new(Vec2::ONE).into()
+            } else {
+                shape::Circle::new(1.0).into()
+            };
+            select(
+                &mut commands,
+                &mut meshes,
+                &mut materials,
+                entity,
+                outline,
+                settings.colors.light_selected,
+            );
+        }
+    }
+}
+
+/// Keeps clicks, scrolling and typing in the panel away from the canvas.
+pub fn capture_panel_input(
+    mut contexts: EguiContexts,
+    settings: Res<Settings>,
+    mut keys: ResMut<Input<KeyCode>>,
+    mut mouse_button: ResMut<Input<MouseButton>>,
+    mut wheel: ResMut<Events<MouseWheel>>,
+) {
+    let ctx = contexts.ctx_mut();
+    if ctx.wants_keyboard_input() {
+        keys.reset_all();
+    }
+    if ctx.wants_pointer_input() || ctx.is_pointer_over_area() {
+        // 押しっぱなしの状態は残して、ドラッグ中の物を取り落とさない
+        let pressed: Vec<MouseButton> = mouse_button.get_just_pressed().copied().collect();
+        for button in pressed {
+            mouse_button.clear_just_pressed(button);
+        }
+        // 光源は離したときに置く
+        for binding in settings.bindings.get(Action::SpawnLight) {
+            if let Binding::Mouse(button) = *binding {
+                mouse_button.clear_just_released(button);
+            }
+        }
+        wheel.clear();
+    }
+}
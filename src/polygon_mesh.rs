@@ -0,0 +1,163 @@
+use bevy::{
+    prelude::*,
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+};
+use geo::algorithm::triangulate_earcut::TriangulateEarcut;
+use geo::{Area, LineString, MultiPolygon, Polygon};
+
+// これより小さい面積の輪は描画しない
+const MIN_AREA: f32 = 1e-3;
+
+fn is_valid_ring(ring: &LineString<f32>) -> bool {
+    ring.0.len() >= 4
+        && ring.coords().all(|c| c.x.is_finite() && c.y.is_finite())
+        && Polygon::new(ring.clone(), Vec::new()).unsigned_area() >= MIN_AREA
+}
+
+/// Collects polygons, including their holes, into a single triangle mesh.
+///
+/// Degenerate rings (too few points, non-finite coordinates or no area) are
+/// skipped: a degenerate exterior drops the whole polygon, a degenerate hole
+/// just the hole.
+#[derive(Default)]
+pub struct PolygonMeshBuilder {
+    origin: Option<Vec2>,
+    positions: Vec<[f32; 3]>,
+    indices: Vec<u32>,
+}
+
+impl PolygonMeshBuilder {
+    pub fn add_polygon(&mut self, polygon: &Polygon<f32>) -> &mut Self {
+        if !is_valid_ring(polygon.exterior()) {
+            return self;
+        }
+        let polygon = Polygon::new(
+            polygon.exterior().clone(),
+            polygon
+                .interiors()
+                .iter()
+                .filter(|interior| is_valid_ring(interior))
+                .cloned()
+                .collect(),
+        );
+
+        let triangulation = polygon.earcut_triangles_raw();
+        if triangulation.triangle_indices.is_empty() {
+            return self;
+        }
+        // 精度のため、最初の頂点を原点にする
+        let origin = *self.origin.get_or_insert(Vec2::new(
+            triangulation.vertices[0],
+            triangulation.vertices[1],
+        ));
+
+        let offset = self.positions.len() as u32;
+        self.positions.extend(
+            triangulation
+                .vertices
+                .chunks_exact(2)
+                .map(|v| [v[0] - origin.x, v[1] - origin.y, 0.0]),
+        );
+        self.indices.extend(
+            triangulation
+                .triangle_indices
+                .iter()
+                .map(|&i| offset + i as u32),
+        );
+        self
+    }
+
+    pub fn add_multi_polygon(&mut self, multi_polygon: &MultiPolygon<f32>) -> &mut Self {
+        for polygon in multi_polygon {
+            self.add_polygon(polygon);
+        }
+        self
+    }
+
+    /// The mesh and the translation to place it at, or `None` if nothing
+    /// drawable was added.
+    pub fn build(self) -> Option<(Vec2, Mesh)> {
+        let origin = self.origin?;
+        let vertex_count = self.positions.len();
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.positions);
+        // Assign a UV coordinate to each vertex.
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0, 0.0]; vertex_count]);
+        // Assign normals (everything points outwards)
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0.0, 0.0, 1.0]; vertex_count]);
+        mesh.set_indices(Some(Indices::U32(self.indices)));
+
+        Some((origin, mesh))
+    }
+
+    /// Like [`Self::build`], with every vertex coloured by `shade` at its world
+    /// position. The colours are interpolated across each triangle.
+    pub fn build_shaded(self, shade: impl Fn(Vec2) -> Color) -> Option<(Vec2, Mesh)> {
+        let origin = self.origin?;
+        let colors: Vec<[f32; 4]> = self
+            .positions
+            .iter()
+            .map(|p| shade(origin + Vec2::new(p[0], p[1])).as_linear_rgba_f32())
+            .collect();
+        let (translation, mut mesh) = self.build()?;
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+        Some((translation, mesh))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::polygon;
+
+    fn triangle_count(mesh: &Mesh) -> usize {
+        mesh.indices().map_or(0, |indices| indices.len() / 3)
+    }
+
+    #[test]
+    fn empty_input_builds_nothing() {
+        assert!(PolygonMeshBuilder::default().build().is_none());
+
+        let mut builder = PolygonMeshBuilder::default();
+        builder
+            .add_multi_polygon(&MultiPolygon::new(Vec::new()))
+            .add_polygon(&polygon![(x: 0., y: 0.), (x: 1., y: 1.), (x: 2., y: 2.)])
+            .add_polygon(&polygon![(x: 0., y: 0.), (x: 1., y: 0.)])
+            .add_polygon(&polygon![(x: 0., y: 0.), (x: f32::NAN, y: 0.), (x: 0., y: 1.)]);
+        assert!(builder.build().is_none());
+    }
+
+    #[test]
+    fn square_with_hole() {
+        let square = polygon!(
+            exterior: [(x: 0., y: 0.), (x: 10., y: 0.), (x: 10., y: 10.), (x: 0., y: 10.)],
+            interiors: [[(x: 4., y: 4.), (x: 6., y: 4.), (x: 6., y: 6.), (x: 4., y: 6.)]],
+        );
+        let mut builder = PolygonMeshBuilder::default();
+        builder.add_polygon(&square);
+        let (translation, mesh) = builder.build().unwrap();
+
+        assert_eq!(translation, Vec2::new(0.0, 0.0));
+        // 外周 4 点と穴 4 点から 8 枚の三角形
+        assert_eq!(triangle_count(&mesh), 8);
+    }
+
+    #[test]
+    fn degenerate_hole_is_ignored() {
+        let square = polygon!(
+            exterior: [(x: 0., y: 0.), (x: 10., y: 0.), (x: 10., y: 10.), (x: 0., y: 10.)],
+            interiors: [[(x: 4., y: 4.), (x: 6., y: 6.), (x: 5., y: 5.)]],
+        );
+        let mut builder = PolygonMeshBuilder::default();
+        builder.add_polygon(&square).add_polygon(&polygon![
+            (x: 20., y: 0.),
+            (x: 30., y: 0.),
+            (x: 30., y: 10.),
+        ]);
+        let (translation, mesh) = builder.build().unwrap();
+
+        assert_eq!(translation, Vec2::new(0.0, 0.0));
+        assert_eq!(triangle_count(&mesh), 3);
+    }
+}
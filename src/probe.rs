@@ -0,0 +1,168 @@
+use bevy::ecs as bevy_ecs;
+use bevy::{prelude::*, window::PrimaryWindow};
+
+use crate::actions::{Action, Actions};
+use crate::raycast::{first_hit, transmission};
+use crate::spatial_index::ObstacleIndex;
+use crate::{Light, SceneScale, WorldCoords, COLOR_PROBE_BLOCKED, COLOR_PROBE_CLEAR};
+
+const TOOLTIP_OFFSET: Vec2 = Vec2::new(16.0, 16.0);
+
+/// Whether hovering shows which lights see the cursor.
+#[derive(Resource, Default)]
+pub struct Probe(pub bool);
+
+#[derive(Component)]
+pub struct ProbeTooltip;
+
+/// How the light of one fixture gets to the probed point.
+#[derive(Debug, PartialEq)]
+pub enum Sight {
+    Clear,
+    /// Through translucent obstacles, letting the given share through.
+    Dimmed(f32),
+    /// Stopped by the obstacle with the given index at the given point.
+    Blocked(usize, Vec2),
+}
+
+pub fn sight(
+    light_position: Vec2,
+    point: Vec2,
+    opaque: &[[Vec2; 4]],
+    translucent: &[([Vec2; 4], f32)],
+) -> Sight {
+    if let Some((index, hit)) = first_hit(light_position, point, opaque) {
+        return Sight::Blocked(index, hit);
+    }
+    match transmission(light_position, point, translucent) {
+        share if share < 1.0 => Sight::Dimmed(share),
+        _ => Sight::Clear,
+    }
+}
+
+pub fn toggle_probe(actions: Res<Actions>, mut probe: ResMut<Probe>) {
+    if actions.just_pressed(Action::ToggleProbe) {
+        probe.0 = !probe.0;
+    }
+}
+
+pub fn spawn_probe_tooltip(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 14.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            padding: UiRect::all(Val::Px(4.0)),
+            ..default()
+        })
+        .with_background_color(Color::rgba(0.0, 0.0, 0.0, 0.7)),
+        ProbeTooltip,
+    ));
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn probe_cursor(
+    probe: Res<Probe>,
+    cursor_position: Res<WorldCoords>,
+    scene_scale: Res<SceneScale>,
+    lights: Query<(Entity, &Transform, Option<&Name>), With<Light>>,
+    obstacle_index: Res<ObstacleIndex>,
+    q_window: Query<&Window, With<PrimaryWindow>>,
+    mut tooltips: Query<(&mut Text, &mut Style, &mut Visibility), With<ProbeTooltip>>,
+    mut gizmos: Gizmos,
+) {
+    let Ok((mut text, mut style, mut visibility)) = tooltips.get_single_mut() else {
+        return;
+    };
+    let cursor = q_window.get_single().ok().and_then(Window::cursor_position);
+    let (true, Some(cursor)) = (probe.0, cursor) else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    *visibility = Visibility::Inherited;
+    style.left = Val::Px(cursor.x + TOOLTIP_OFFSET.x);
+    style.top = Val::Px(cursor.y + TOOLTIP_OFFSET.y);
+
+    let (opaque, translucent): (Vec<_>, Vec<_>) = obstacle_index
+        .iter()
+        .partition(|obstacle| obstacle.transmittance == 0.0);
+    let translucent: Vec<([Vec2; 4], f32)> = translucent
+        .into_iter()
+        .map(|obstacle| (obstacle.vertices, obstacle.transmittance))
+        .collect();
+    let opaque_vertices: Vec<[Vec2; 4]> = opaque.iter().map(|obstacle| obstacle.vertices).collect();
+
+    let point = cursor_position.0;
+    let mut lines = Vec::new();
+    for (entity, transform, name) in lights.iter() {
+        let light = transform.translation.truncate();
+        let distance = light.distance(point) * scene_scale.0;
+        let name = name.map_or_else(|| format!("Light {}", entity.index()), |n| n.to_string());
+        let status = match sight(light, point, &opaque_vertices, &translucent) {
+            Sight::Clear => {
+                gizmos.line_2d(light, point, COLOR_PROBE_CLEAR);
+                "lit".to_string()
+            }
+            Sight::Dimmed(share) => {
+                gizmos.line_2d(light, point, COLOR_PROBE_CLEAR.with_a(0.5));
+                format!("through glass, {:.0}%", share * 100.0)
+            }
+            Sight::Blocked(index, hit) => {
+                gizmos.line_2d(light, hit, COLOR_PROBE_BLOCKED);
+                gizmos.line_2d(hit, point, COLOR_PROBE_BLOCKED.with_a(0.3));
+                gizmos.circle_2d(hit, 4.0, COLOR_PROBE_BLOCKED);
+                let vertices = opaque[index].vertices;
+                gizmos.linestrip_2d(
+                    vertices.iter().chain(vertices.first()).copied(),
+                    COLOR_PROBE_BLOCKED,
+                );
+                "blocked".to_string()
+            }
+        };
+        lines.push(format!("{name}: {distance:.1} m, {status}"));
+    }
+    text.sections[0].value = if lines.is_empty() {
+        "No lights".to_string()
+    } else {
+        lines.join("\n")
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sight_reports_the_blocking_obstacle() {
+        let square = |center: Vec2| {
+            [
+                center + Vec2::new(-10.0, -10.0),
+                center + Vec2::new(10.0, -10.0),
+                center + Vec2::new(10.0, 10.0),
+                center + Vec2::new(-10.0, 10.0),
+            ]
+        };
+        let opaque = [square(Vec2::new(0.0, 100.0)), square(Vec2::new(50.0, 0.0))];
+        let glass = [(square(Vec2::new(0.0, -50.0)), 0.5)];
+        let light = Vec2::ZERO;
+
+        assert_eq!(
+            sight(light, Vec2::new(-100.0, 0.0), &opaque, &glass),
+            Sight::Clear
+        );
+        assert_eq!(
+            sight(light, Vec2::new(100.0, 0.0), &opaque, &glass),
+            Sight::Blocked(1, Vec2::new(40.0, 0.0))
+        );
+        assert_eq!(
+            sight(light, Vec2::new(0.0, -100.0), &opaque, &glass),
+            Sight::Dimmed(0.5)
+        );
+    }
+}
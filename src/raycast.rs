@@ -0,0 +1,199 @@
+//! Reference lighting by direct ray casting, used to check the shadow polygons.
+
+use bevy::prelude::*;
+
+fn cross(u: Vec2, v: Vec2) -> f32 {
+    u.x * v.y - u.y * v.x
+}
+
+/// The parameter `t` in `[0, 1]` at which segment `a0 -> a1` meets segment
+/// `b0 -> b1`, if they meet. Collinear overlapping segments meet at the first
+/// shared point.
+fn segment_intersection(a0: Vec2, a1: Vec2, b0: Vec2, b1: Vec2) -> Option<f32> {
+    let r = a1 - a0;
+    let s = b1 - b0;
+    let denominator = cross(r, s);
+    let offset = b0 - a0;
+    if denominator == 0.0 {
+        if cross(offset, r) != 0.0 {
+            return None;
+        }
+        // 同一直線上
+        let length_2 = r.length_squared();
+        if length_2 == 0.0 {
+            return None;
+        }
+        let t0 = offset.dot(r) / length_2;
+        let t1 = (b1 - a0).dot(r) / length_2;
+        let (lo, hi) = (t0.min(t1), t0.max(t1));
+        return (hi >= 0.0 && lo <= 1.0).then_some(lo.max(0.0));
+    }
+    let t = cross(offset, s) / denominator;
+    let u = cross(offset, r) / denominator;
+    ((0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u)).then_some(t)
+}
+
+/// The nearest obstacle edge crossed by the segment from `light_position` to
+/// `point`, as the index of the obstacle and the point where it is hit.
+pub fn first_hit(
+    light_position: Vec2,
+    point: Vec2,
+    obstacles: &[[Vec2; 4]],
+) -> Option<(usize, Vec2)> {
+    obstacles
+        .iter()
+        .enumerate()
+        .flat_map(|(i, vertices)| {
+            (0..vertices.len()).filter_map(move |j| {
+                let u = vertices[j];
+                let v = vertices[(j + 1) % vertices.len()];
+                segment_intersection(light_position, point, u, v).map(|t| (i, t))
+            })
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, t)| (i, light_position.lerp(point, t)))
+}
+
+/// Whether `point` is lit by a point light at `light_position`.
+pub fn is_lit(light_position: Vec2, point: Vec2, obstacles: &[[Vec2; 4]]) -> bool {
+    first_hit(light_position, point, obstacles).is_none()
+}
+
+/// The share of the light from `light_position` that reaches `point` through
+/// translucent obstacles, given with their transmittance.
+pub fn transmission(light_position: Vec2, point: Vec2, obstacles: &[([Vec2; 4], f32)]) -> f32 {
+    obstacles
+        .iter()
+        .filter(|(vertices, _)| !is_lit(light_position, point, std::slice::from_ref(vertices)))
+        .map(|&(_, transmittance)| transmittance)
+        .product()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spatial_index::{LightReach, ObstacleIndex};
+    use crate::{
+        calculate_light_shadow, calculate_shadow_layers, calculate_vertices, WORLD_HEIGHT,
+        WORLD_WIDTH,
+    };
+    use geo::{Intersects, LineString, MultiPolygon, Point, Polygon};
+    use proptest::prelude::*;
+
+    // 境界付近は丸め誤差で判定が揺れるので、この半径内で判定が一定の点だけを比べる
+    const TOLERANCE: f32 = 1.0;
+
+    const WORLD_BOUNDARY: (Vec2, Vec2) = (
+        Vec2::new(-WORLD_WIDTH / 2., -WORLD_HEIGHT / 2.),
+        Vec2::new(WORLD_WIDTH / 2., WORLD_HEIGHT / 2.),
+    );
+
+    fn world_point() -> impl Strategy<Value = Vec2> {
+        (
+            -WORLD_WIDTH / 2.0..WORLD_WIDTH / 2.0,
+            -WORLD_HEIGHT / 2.0..WORLD_HEIGHT / 2.0,
+        )
+            .prop_map(|(x, y)| Vec2::new(x, y))
+    }
+
+    fn obstacle() -> impl Strategy<Value = Transform> {
+        (world_point(), 5.0f32..150.0, 5.0f32..150.0, 0.0f32..360.0).prop_map(
+            |(center, width, height, angle)| {
+                Transform::from_translation(center.extend(0.0))
+                    .with_scale(Vec3::new(width, height, 1.0))
+                    .with_rotation(Quat::from_rotation_z(angle.to_radians()))
+            },
+        )
+    }
+
+    fn is_stable(point: Vec2, classify: impl Fn(Vec2) -> bool) -> bool {
+        let expected = classify(point);
+        (0..8).all(|i| {
+            let offset = Vec2::from_angle(i as f32 * std::f32::consts::FRAC_PI_4) * TOLERANCE;
+            classify(point + offset) == expected
+        })
+    }
+
+    fn contains(shadow: &MultiPolygon<f32>, point: Vec2) -> bool {
+        shadow.intersects(&Point::new(point.x, point.y))
+    }
+
+    #[test]
+    fn segment_crossing_an_edge_is_blocked() {
+        let obstacle = [
+            Vec2::new(-10.0, -10.0),
+            Vec2::new(10.0, -10.0),
+            Vec2::new(10.0, 10.0),
+            Vec2::new(-10.0, 10.0),
+        ];
+        let light = Vec2::new(-100.0, 0.0);
+        assert!(is_lit(light, Vec2::new(-50.0, 0.0), &[obstacle]));
+        assert!(is_lit(light, Vec2::new(100.0, 50.0), &[obstacle]));
+        assert!(!is_lit(light, Vec2::new(0.0, 0.0), &[obstacle]));
+        assert_eq!(
+            first_hit(light, Vec2::new(100.0, 0.0), &[obstacle]),
+            Some((0, Vec2::new(-10.0, 0.0)))
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn shadow_layers_agree_with_ray_casting(
+            lights in prop::collection::vec(world_point(), 1..4),
+            obstacles in prop::collection::vec(obstacle(), 1..7),
+            points in prop::collection::vec(world_point(), 32),
+        ) {
+            let vertices: Vec<[Vec2; 4]> = obstacles.iter().map(calculate_vertices).collect();
+            let polygons: Vec<Polygon<f32>> = vertices
+                .iter()
+                .map(|v| Polygon::new(LineString::from_iter(v.iter().map(|u| u.to_array())), Vec::new()))
+                .collect();
+            let inside_obstacle = |p: Vec2| polygons.iter().any(|polygon| polygon.intersects(&Point::new(p.x, p.y)));
+            prop_assume!(lights.iter().all(|&light| !inside_obstacle(light) && is_stable(light, inside_obstacle)));
+            let mut index = ObstacleIndex::default();
+            for (i, transform) in obstacles.iter().enumerate() {
+                index.upsert(Entity::from_raw(i as u32), transform, 0.0);
+            }
+
+            let shadows: Vec<MultiPolygon<f32>> = lights
+                .iter()
+                .map(|&light| {
+                    let visible: Vec<[Vec2; 4]> = index
+                        .visible_from(light, &LightReach::default(), &WORLD_BOUNDARY)
+                        .into_iter()
+                        .map(|obstacle| obstacle.vertices)
+                        .collect();
+                    calculate_light_shadow(light, &visible, WORLD_BOUNDARY)
+                })
+                .collect();
+            let layers = calculate_shadow_layers(shadows.clone());
+
+            for point in points {
+                for (&light, shadow) in lights.iter().zip(&shadows) {
+                    let reference = |p: Vec2| !is_lit(light, p, &vertices);
+                    let polygon = |p: Vec2| contains(shadow, p);
+                    if is_stable(point, reference) && is_stable(point, polygon) {
+                        prop_assert_eq!(reference(point), polygon(point), "light {} at {}", light, point);
+                    }
+                }
+
+                let unlit = |p: Vec2| lights.iter().filter(|&&light| !is_lit(light, p, &vertices)).count();
+                let in_union = |p: Vec2| contains(&layers.union, p);
+                let in_intersection = |p: Vec2| {
+                    layers.intersection.as_ref().is_some_and(|shadow| contains(shadow, p))
+                };
+                if is_stable(point, |p| unlit(p) > 0) && is_stable(point, in_union) {
+                    prop_assert_eq!(unlit(point) > 0, in_union(point), "union at {}", point);
+                }
+                if is_stable(point, |p| unlit(p) == lights.len()) && is_stable(point, in_intersection) {
+                    prop_assert_eq!(
+                        unlit(point) == lights.len(),
+                        in_intersection(point),
+                        "intersection at {}",
+                        point
+                    );
+                }
+            }
+        }
+    }
+}
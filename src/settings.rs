@@ -0,0 +1,382 @@
+use bevy::app::AppExit;
+use bevy::ecs as bevy_ecs;
+use bevy::input::{keyboard::KeyboardInput, mouse::MouseButtonInput, ButtonState};
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use serde::{Deserialize, Serialize};
+
+use crate::actions::{Action, Binding, Bindings};
+use crate::solo::Muted;
+use crate::{
+    Floor, Light, Obstacle, ShadowJobs, COLOR_LIGHT, COLOR_LIGHT_SELECTED, COLOR_NORMAL,
+    COLOR_OBSTACLE, COLOR_SHADOW, COLOR_SHADOW_INTERSECTION, COLOR_SHADOW_UNION, LIGHT_SIZE,
+    SETTINGS_SAVE_DELAY,
+};
+
+/// The colour as `#rrggbbaa`.
+pub fn to_hex(color: Color) -> String {
+    let [r, g, b, a] = color.as_rgba_u8();
+    format!("#{r:02x}{g:02x}{b:02x}{a:02x}")
+}
+
+pub fn from_hex(hex: &str) -> Result<Color, bevy::render::color::HexColorError> {
+    Color::hex(hex.trim_start_matches('#'))
+}
+
+/// Colours are written as `#rrggbbaa` so the file stays easy to edit.
+mod hex_color {
+    use bevy::prelude::Color;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &Color, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::to_hex(*color))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        super::from_hex(&hex).map_err(D::Error::custom)
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Palette {
+    #[serde(with = "hex_color")]
+    pub floor: Color,
+    #[serde(with = "hex_color")]
+    pub shadow: Color,
+    #[serde(with = "hex_color")]
+    pub shadow_union: Color,
+    #[serde(with = "hex_color")]
+    pub shadow_intersection: Color,
+    #[serde(with = "hex_color")]
+    pub light: Color,
+    #[serde(with = "hex_color")]
+    pub light_selected: Color,
+    #[serde(with = "hex_color")]
+    pub obstacle: Color,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            floor: COLOR_NORMAL,
+            shadow: COLOR_SHADOW,
+            shadow_union: COLOR_SHADOW_UNION,
+            shadow_intersection: COLOR_SHADOW_INTERSECTION,
+            light: COLOR_LIGHT,
+            light_selected: COLOR_LIGHT_SELECTED,
+            obstacle: COLOR_OBSTACLE,
+        }
+    }
+}
+
+impl Palette {
+    fn iter_mut(&mut self) -> [(&'static str, &mut Color); 7] {
+        [
+            ("Floor", &mut self.floor),
+            ("Outside the room", &mut self.shadow),
+            ("Shadow of some lights", &mut self.shadow_union),
+            ("Shadow of all lights", &mut self.shadow_intersection),
+            ("Light", &mut self.light),
+            ("Selection", &mut self.light_selected),
+            ("Obstacle", &mut self.obstacle),
+        ]
+    }
+}
+
+/// User preferences, kept in `settings.toml` in the user's config directory.
+/// Missing entries fall back to the defaults.
+#[derive(Resource, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub colors: Palette,
+    pub zoom_min: f32,
+    pub zoom_max: f32,
+    /// Arrow-key panning speed in room widths per second.
+    pub pan_speed: f32,
+    pub light_size: f32,
+    pub bindings: Bindings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            colors: Palette::default(),
+            zoom_min: 0.2,
+            zoom_max: 3.0,
+            pan_speed: 0.5,
+            light_size: LIGHT_SIZE,
+            bindings: Bindings::default(),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Settings {
+    fn path() -> Option<std::path::PathBuf> {
+        Some(dirs::config_dir()?.join("museum").join("settings.toml"))
+    }
+
+    /// The saved settings, or the defaults if there are none or they can't
+    /// be read.
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text).unwrap_or_else(|error| {
+                warn!("Ignoring {}: {error}", path.display());
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let path = Self::path().ok_or("no config directory")?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+// ブラウザでは保存先がないので毎回既定値
+#[cfg(target_arch = "wasm32")]
+impl Settings {
+    pub fn load() -> Self {
+        Self::default()
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+}
+
+/// Whether the settings window is open.
+#[derive(Resource, Default)]
+pub struct ShowSettings(pub bool);
+
+fn edit_value(ui: &mut egui::Ui, label: &str, value: &mut f32, range: (f32, f32), speed: f64) {
+    ui.label(label);
+    ui.add(
+        egui::DragValue::new(value)
+            .speed(speed)
+            .clamp_range(range.0..=range.1),
+    );
+    ui.end_row();
+}
+
+pub fn settings_window(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Settings>,
+    mut show: ResMut<ShowSettings>,
+    mut key_events: EventReader<KeyboardInput>,
+    mut mouse_events: EventReader<MouseButtonInput>,
+    gamepad_button: Res<Input<GamepadButton>>,
+    mut rebinding: Local<Option<Action>>,
+) {
+    // 入力欄が使っているキーも拾えるよう、イベントから読む
+    let key = key_events
+        .iter()
+        .filter(|event| event.state.is_pressed())
+        .find_map(|event| event.key_code)
+        .map(Binding::Key);
+    // 窓の上のクリックは取り消しとして扱う
+    let over_window = contexts.ctx_mut().is_pointer_over_area();
+    let button = mouse_events
+        .iter()
+        .find(|event| event.state == ButtonState::Pressed && !over_window)
+        .map(|event| Binding::Mouse(event.button));
+    let pad = gamepad_button
+        .get_just_pressed()
+        .next()
+        .map(|button| Binding::Gamepad(button.button_type));
+    let pressed = key.or(button).or(pad);
+    if !show.0 {
+        *rebinding = None;
+        return;
+    }
+    let mut open = true;
+    // 写しを編集して、変わったときだけ書き戻す
+    let mut draft = settings.clone();
+    egui::Window::new("Settings")
+        .open(&mut open)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.heading("Colours");
+            egui::Grid::new("colors").num_columns(2).show(ui, |ui| {
+                for (label, color) in draft.colors.iter_mut() {
+                    ui.label(label);
+                    let [r, g, b, a] = color.as_rgba_f32();
+                    let mut rgb = [r, g, b];
+                    if ui.color_edit_button_rgb(&mut rgb).changed() {
+                        *color = Color::rgba(rgb[0], rgb[1], rgb[2], a);
+                    }
+                    ui.end_row();
+                }
+            });
+
+            ui.heading("View");
+            egui::Grid::new("view").num_columns(2).show(ui, |ui| {
+                let zoom_max = draft.zoom_max;
+                edit_value(
+                    ui,
+                    "Closest zoom",
+                    &mut draft.zoom_min,
+                    (0.05, zoom_max),
+                    0.01,
+                );
+                let zoom_min = draft.zoom_min;
+                edit_value(
+                    ui,
+                    "Farthest zoom",
+                    &mut draft.zoom_max,
+                    (zoom_min, 20.0),
+                    0.05,
+                );
+                edit_value(ui, "Pan speed", &mut draft.pan_speed, (0.05, 5.0), 0.01);
+                edit_value(ui, "Light size", &mut draft.light_size, (1.0, 100.0), 0.5);
+            });
+
+            ui.heading("Controls");
+            ui.label("Click a binding to remove it.");
+            egui::ScrollArea::vertical()
+                .max_height(240.0)
+                .show(ui, |ui| {
+                    egui::Grid::new("controls").num_columns(2).show(ui, |ui| {
+                        for action in Action::ALL {
+                            ui.label(action.description());
+                            let bindings = draft.bindings.get_mut(action);
+                            if *rebinding == Some(action) {
+                                if let Some(pressed) = pressed {
+                                    if !bindings.contains(&pressed) {
+                                        bindings.push(pressed);
+                                    }
+                                    *rebinding = None;
+                                }
+                            }
+                            ui.horizontal(|ui| {
+                                bindings.retain(|binding| !ui.button(binding.label()).clicked());
+                                let text = if *rebinding == Some(action) {
+                                    "Press a key or button…"
+                                } else {
+                                    "+"
+                                };
+                                if ui.button(text).clicked() {
+                                    *rebinding = match *rebinding {
+                                        Some(current) if current == action => None,
+                                        _ => Some(action),
+                                    };
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    });
+                });
+
+            ui.separator();
+            if ui.button("Restore defaults").clicked() {
+                draft = Settings::default();
+            }
+        });
+    show.0 = open;
+    if draft != *settings {
+        *settings = draft;
+    }
+}
+
+/// Saves the settings once they have stopped changing for a moment, so
+/// dragging a slider doesn't write the file every frame, and straight away
+/// when the app is closing.
+pub fn save_settings(
+    settings: Res<Settings>,
+    time: Res<Time>,
+    mut exit: EventReader<AppExit>,
+    mut pending: Local<Option<Timer>>,
+) {
+    if settings.is_changed() && !settings.is_added() {
+        *pending = Some(Timer::from_seconds(SETTINGS_SAVE_DELAY, TimerMode::Once));
+    }
+    let closing = exit.iter().count() > 0;
+    let Some(timer) = pending.as_mut() else {
+        return;
+    };
+    if !timer.tick(time.delta()).finished() && !closing {
+        return;
+    }
+    *pending = None;
+    if let Err(error) = settings.save() {
+        warn!("Couldn't save the settings: {error}");
+    }
+}
+
+/// Recolours the scene when the palette changes.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn apply_palette(
+    settings: Res<Settings>,
+    clear_color: Option<ResMut<ClearColor>>,
+    mut floors: Query<&mut Sprite, (With<Floor>, Without<Obstacle>)>,
+    mut obstacles: Query<&mut Sprite, (With<Obstacle>, Without<Floor>)>,
+    lights: Query<&Handle<ColorMaterial>, (With<Light>, Without<Muted>)>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut jobs: ResMut<ShadowJobs>,
+    mut palette: Local<Palette>,
+) {
+    if settings.colors == *palette {
+        return;
+    }
+    *palette = settings.colors.clone();
+    if let Some(mut clear_color) = clear_color {
+        clear_color.0 = palette.shadow;
+    }
+    for mut sprite in floors.iter_mut() {
+        sprite.color = palette.floor;
+    }
+    // 半透明の障害物は透明度を残す
+    for mut sprite in obstacles.iter_mut() {
+        sprite.color = palette.obstacle.with_a(sprite.color.a());
+    }
+    for material in lights.iter() {
+        if let Some(material) = materials.get_mut(material) {
+            material.color = palette.light;
+        }
+    }
+    jobs.outdated = true;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_round_trip_and_fill_in_defaults() {
+        let mut settings = Settings::default();
+        settings.colors.light = Color::rgb_u8(255, 128, 0);
+        settings.bindings.get_mut(Action::Delete)[0] = Binding::Key(KeyCode::Back);
+        let text = toml::to_string_pretty(&settings).unwrap();
+        assert!(text.contains("light = \"#ff8000ff\""));
+        // 色は 8 ビットに丸めて保存する
+        let loaded: Settings = toml::from_str(&text).unwrap();
+        assert_eq!(toml::to_string_pretty(&loaded).unwrap(), text);
+        assert_eq!(
+            loaded.bindings.get(Action::Delete),
+            [Binding::Key(KeyCode::Back)]
+        );
+
+        let partial: Settings =
+            toml::from_str("pan_speed = 1.5\n[bindings]\nreset_zoom = [{ Key = \"Home\" }]\n")
+                .unwrap();
+        assert_eq!(partial.pan_speed, 1.5);
+        assert_eq!(
+            partial.bindings.get(Action::ResetZoom),
+            [Binding::Key(KeyCode::Home)]
+        );
+        assert_eq!(
+            partial.bindings.get(Action::Delete),
+            [Binding::Key(KeyCode::Delete)]
+        );
+        assert_eq!(partial.zoom_max, 3.0);
+    }
+}
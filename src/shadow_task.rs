@@ -0,0 +1,54 @@
+#[cfg(not(target_arch = "wasm32"))]
+use bevy::tasks::{AsyncComputeTaskPool, Task};
+#[cfg(not(target_arch = "wasm32"))]
+use futures_lite::future;
+
+/// A computation running on the `AsyncComputeTaskPool`.
+///
+/// wasm has no worker threads to run it on, so there the work is done
+/// immediately when the task is spawned.
+pub struct ShadowTask<T> {
+    #[cfg(not(target_arch = "wasm32"))]
+    task: Option<Task<T>>,
+    result: Option<T>,
+}
+
+impl<T: Send + 'static> ShadowTask<T> {
+    pub fn spawn(f: impl FnOnce() -> T + Send + 'static) -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            Self {
+                task: Some(AsyncComputeTaskPool::get().spawn(async move { f() })),
+                result: None,
+            }
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            Self { result: Some(f()) }
+        }
+    }
+
+    pub fn is_finished(&mut self) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(task) = &mut self.task {
+            if let Some(result) = future::block_on(future::poll_once(task)) {
+                self.result = Some(result);
+                self.task = None;
+            }
+        }
+        self.result.is_some()
+    }
+
+    /// Blocks until the result is ready.
+    #[cfg(test)]
+    pub fn wait(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(task) = self.task.take() {
+            self.result = Some(future::block_on(task));
+        }
+    }
+
+    pub fn into_result(self) -> Option<T> {
+        self.result
+    }
+}
@@ -0,0 +1,116 @@
+use bevy::prelude::*;
+use geo::{Area, MinimumRotatedRect, Polygon};
+
+use crate::{Obstacle, OBSTACLE_Z};
+
+/// The rectangle an obstacle covers, as its sprite's transform describes it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ObstacleRect {
+    pub center: Vec2,
+    pub size: Vec2,
+    pub angle: f32,
+}
+
+impl ObstacleRect {
+    /// A wall `thickness` thick along the segment from `from` to `to`.
+    pub fn along(from: Vec2, to: Vec2, thickness: f32) -> Self {
+        let line = to - from;
+        Self {
+            center: (from + to) / 2.0,
+            size: Vec2::new(line.length(), thickness),
+            angle: line.y.atan2(line.x),
+        }
+    }
+
+    pub fn transform(&self) -> Transform {
+        Transform::from_translation(self.center.extend(OBSTACLE_Z))
+            .with_scale(self.size.extend(1.0))
+            .with_rotation(Quat::from_rotation_z(self.angle))
+    }
+}
+
+/// Obstacles that block light like `polygon` does: the smallest rectangle
+/// around it if it fills at least `fill` of that, or else thin walls along
+/// its outline, which cast the same shadow as the filled shape.
+pub fn polygon_to_rects(polygon: &Polygon<f32>, fill: f32, thickness: f32) -> Vec<ObstacleRect> {
+    let points: Vec<Vec2> = polygon
+        .exterior()
+        .points()
+        .map(|p| Vec2::new(p.x(), p.y()))
+        .collect();
+    if let Some(rect) = polygon.minimum_rotated_rect() {
+        let corners: Vec<Vec2> = rect
+            .exterior()
+            .points()
+            .map(|p| Vec2::new(p.x(), p.y()))
+            .collect();
+        if corners.len() >= 4 && polygon.unsigned_area() >= fill * rect.unsigned_area() {
+            let (width, height) = (corners[1] - corners[0], corners[2] - corners[1]);
+            return vec![ObstacleRect {
+                center: (corners[0] + corners[2]) / 2.0,
+                size: Vec2::new(width.length(), height.length()),
+                angle: width.y.atan2(width.x),
+            }];
+        }
+    }
+    points
+        .windows(2)
+        .filter(|edge| edge[0] != edge[1])
+        .map(|edge| ObstacleRect::along(edge[0], edge[1], thickness))
+        .collect()
+}
+
+pub fn spawn_obstacle(
+    commands: &mut Commands,
+    rect: ObstacleRect,
+    color: Color,
+    name: impl Into<std::borrow::Cow<'static, str>>,
+) -> Entity {
+    commands
+        .spawn((
+            SpriteBundle {
+                sprite: Sprite { color, ..default() },
+                transform: rect.transform(),
+                ..default()
+            },
+            Obstacle,
+            Name::new(name),
+        ))
+        .id()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::{polygon, Rotate};
+
+    #[test]
+    fn rectangles_stay_whole_and_other_shapes_become_walls() {
+        let square: Polygon<f32> =
+            polygon![(x: 0.0, y: 0.0), (x: 40.0, y: 0.0), (x: 40.0, y: 20.0), (x: 0.0, y: 20.0)]
+                .rotate_around_centroid(30.0);
+        let rects = polygon_to_rects(&square, 0.9, 2.0);
+        assert_eq!(rects.len(), 1);
+        assert!(rects[0].center.abs_diff_eq(Vec2::new(20.0, 10.0), 1e-3));
+        assert!((rects[0].size.x * rects[0].size.y - 800.0).abs() < 1e-1);
+
+        let ell: Polygon<f32> = polygon![
+            (x: 0.0, y: 0.0),
+            (x: 40.0, y: 0.0),
+            (x: 40.0, y: 10.0),
+            (x: 10.0, y: 10.0),
+            (x: 10.0, y: 40.0),
+            (x: 0.0, y: 40.0),
+        ];
+        let walls = polygon_to_rects(&ell, 0.9, 2.0);
+        assert_eq!(walls.len(), 6);
+        assert_eq!(
+            walls[0],
+            ObstacleRect {
+                center: Vec2::new(20.0, 0.0),
+                size: Vec2::new(40.0, 2.0),
+                angle: 0.0
+            }
+        );
+    }
+}
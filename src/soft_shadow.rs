@@ -0,0 +1,170 @@
+use bevy::ecs as bevy_ecs;
+use bevy::prelude::*;
+use geo::MultiPolygon;
+
+use crate::calculate_shadow_polygon_from_obstacle;
+use crate::geo_scaled::ScaledBooleanOps;
+use crate::polygon_mesh::PolygonMeshBuilder;
+use crate::raycast::is_lit;
+
+// 面光源を近似する点光源の数
+const SAMPLES: usize = 12;
+// 2π (1 - 1/φ)
+const GOLDEN_ANGLE: f32 = 2.399_963;
+
+/// The light-emitting part of a fixture.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub enum Emitter {
+    Point,
+    /// A disc with the given radius, sampled evenly over its area.
+    Disc(f32),
+    /// A segment from `-half` to `half` around the light's position.
+    Segment(Vec2),
+}
+
+impl Emitter {
+    /// Points spread over the emitter, used as point lights.
+    pub fn samples(&self, center: Vec2) -> Vec<Vec2> {
+        match *self {
+            Emitter::Point => vec![center],
+            // ひまわり状に並べて円盤全体に散らす
+            Emitter::Disc(radius) => (0..SAMPLES)
+                .map(|i| {
+                    let distance = ((i as f32 + 0.5) / SAMPLES as f32).sqrt();
+                    center + Vec2::from_angle(GOLDEN_ANGLE * i as f32) * radius * distance
+                })
+                .collect(),
+            Emitter::Segment(half) => (0..SAMPLES)
+                .map(|i| center + half * (2.0 * i as f32 / (SAMPLES - 1) as f32 - 1.0))
+                .collect(),
+        }
+    }
+}
+
+pub struct SoftShadow {
+    /// Where the whole emitter is hidden.
+    pub umbra: MultiPolygon<f32>,
+    /// Where only a part of the emitter is hidden.
+    pub penumbra: MultiPolygon<f32>,
+}
+
+fn is_inside(vertices: &[Vec2; 4], point: Vec2) -> bool {
+    (0..4).all(|i| {
+        let edge = vertices[(i + 1) % 4] - vertices[i];
+        edge.perp_dot(point - vertices[i]) >= 0.0
+    })
+}
+
+/// Drops the samples buried inside an obstacle, which light nothing.
+pub fn unblocked_samples(samples: Vec<Vec2>, obstacles: &[[Vec2; 4]]) -> Vec<Vec2> {
+    samples
+        .into_iter()
+        .filter(|&sample| !obstacles.iter().any(|vertices| is_inside(vertices, sample)))
+        .collect()
+}
+
+/// The umbra and penumbra of the obstacles for an emitter sampled at
+/// `samples`. A point is in the umbra if every sample is hidden, whichever
+/// obstacle hides it.
+pub fn calculate_soft_shadow(
+    samples: &[Vec2],
+    obstacles: &[[Vec2; 4]],
+    world_boundary: (Vec2, Vec2),
+) -> SoftShadow {
+    let shadows: Vec<MultiPolygon<f32>> = samples
+        .iter()
+        .map(|&sample| {
+            obstacles
+                .iter()
+                .map(|vertices| {
+                    calculate_shadow_polygon_from_obstacle(sample, vertices, world_boundary)
+                })
+                .fold(MultiPolygon::new(Vec::new()), |fold, polygon| {
+                    fold.scaled_union(&MultiPolygon::new(vec![polygon]), 1e1)
+                })
+        })
+        .collect();
+    let umbra = shadows
+        .iter()
+        .cloned()
+        .reduce(|fold, shadow| fold.scaled_intersection(&shadow, 1e1))
+        .unwrap_or_else(|| MultiPolygon::new(Vec::new()));
+    let outline = shadows
+        .iter()
+        .fold(MultiPolygon::new(Vec::new()), |fold, shadow| {
+            fold.scaled_union(shadow, 1e1)
+        });
+    SoftShadow {
+        penumbra: outline.scaled_difference(&umbra, 1e1),
+        umbra,
+    }
+}
+
+/// A mesh of the penumbra whose opacity follows the share of the emitter
+/// hidden at each vertex, from clear at the lit edge to `color` at the umbra.
+pub fn create_penumbra_mesh(
+    penumbra: &MultiPolygon<f32>,
+    samples: &[Vec2],
+    obstacles: &[[Vec2; 4]],
+    color: Color,
+) -> Option<(Vec2, Mesh)> {
+    let mut builder = PolygonMeshBuilder::default();
+    builder.add_multi_polygon(penumbra);
+    builder.build_shaded(|point| {
+        let hidden = samples
+            .iter()
+            .filter(|&&sample| !is_lit(sample, point, obstacles))
+            .count();
+        color.with_a(color.a() * hidden as f32 / samples.len() as f32)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::{Intersects, Point};
+
+    #[test]
+    fn disc_light_has_penumbra_around_umbra() {
+        let world_boundary = (Vec2::new(-480.0, -360.0), Vec2::new(480.0, 360.0));
+        let obstacle = [
+            Vec2::new(90.0, -10.0),
+            Vec2::new(110.0, -10.0),
+            Vec2::new(110.0, 10.0),
+            Vec2::new(90.0, 10.0),
+        ];
+        let samples = Emitter::Disc(5.0).samples(Vec2::ZERO);
+        let SoftShadow { umbra, penumbra } =
+            calculate_soft_shadow(&samples, &[obstacle], world_boundary);
+
+        let at = |x: f32, y: f32| Point::new(x, y);
+        // 真後ろは本影、本影の縁の外側は半影、十分外側は影なし
+        assert!(umbra.intersects(&at(300.0, 0.0)));
+        assert!(!penumbra.intersects(&at(300.0, 0.0)));
+        assert!(penumbra.intersects(&at(400.0, 40.0)));
+        assert!(!umbra.intersects(&at(400.0, 40.0)));
+        assert!(!umbra.intersects(&at(400.0, 100.0)));
+        assert!(!penumbra.intersects(&at(400.0, 100.0)));
+    }
+
+    #[test]
+    fn abutting_obstacles_share_an_umbra() {
+        let world_boundary = (Vec2::new(-480.0, -360.0), Vec2::new(480.0, 360.0));
+        let lower = [
+            Vec2::new(90.0, -10.0),
+            Vec2::new(110.0, -10.0),
+            Vec2::new(110.0, 0.0),
+            Vec2::new(90.0, 0.0),
+        ];
+        let upper = lower.map(|v| v + Vec2::new(0.0, 10.0));
+        let samples = Emitter::Disc(5.0).samples(Vec2::ZERO);
+        assert!(samples.iter().all(|sample| sample.length() <= 5.0));
+        let SoftShadow { umbra, penumbra } =
+            calculate_soft_shadow(&samples, &[lower, upper], world_boundary);
+
+        // どの標本もどちらかの障害物に隠れる
+        let behind_the_seam = Point::new(300.0, 2.0);
+        assert!(umbra.intersects(&behind_the_seam));
+        assert!(!penumbra.intersects(&behind_the_seam));
+    }
+}
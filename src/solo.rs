@@ -0,0 +1,103 @@
+use bevy::ecs as bevy_ecs;
+use bevy::prelude::*;
+
+use crate::actions::{Action, Actions};
+use crate::settings::Settings;
+use crate::{Light, Selected, COLOR_LIGHT_MUTED};
+
+/// A light left out of the shadow computation without deleting it.
+#[derive(Component)]
+pub struct Muted;
+
+/// The light whose visibility is shown on its own, if any.
+#[derive(Resource, Default)]
+pub struct Solo(pub Option<Entity>);
+
+impl Solo {
+    /// Whether the light takes part in the shadow computation. Soloing a
+    /// light overrides muting.
+    pub fn admits(&self, light: Entity, muted: bool) -> bool {
+        match self.0 {
+            Some(solo) => solo == light,
+            None => !muted,
+        }
+    }
+
+    pub fn toggle(&mut self, light: Entity) {
+        self.0 = (self.0 != Some(light)).then_some(light);
+    }
+}
+
+pub fn toggle_muted(commands: &mut Commands, light: Entity, muted: bool) {
+    if muted {
+        commands.entity(light).remove::<Muted>();
+    } else {
+        commands.entity(light).insert(Muted);
+    }
+}
+
+/// O solos the selected light and X mutes it.
+#[allow(clippy::type_complexity)]
+pub fn solo_and_mute_selected(
+    mut commands: Commands,
+    lights: Query<(Entity, Option<&Muted>), (With<Light>, With<Selected>)>,
+    actions: Res<Actions>,
+    mut solo: ResMut<Solo>,
+) {
+    for (entity, muted) in lights.iter() {
+        if actions.just_pressed(Action::Solo) {
+            solo.toggle(entity);
+        }
+        if actions.just_pressed(Action::Mute) {
+            toggle_muted(&mut commands, entity, muted.is_some());
+        }
+    }
+}
+
+pub fn clear_removed_solo(mut solo: ResMut<Solo>, mut removed: RemovedComponents<Light>) {
+    for entity in removed.iter() {
+        if solo.0 == Some(entity) {
+            solo.0 = None;
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn tint_muted_lights(
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    lights: Query<(Entity, &Handle<ColorMaterial>, Option<Ref<Muted>>), With<Light>>,
+    mut removed: RemovedComponents<Muted>,
+    settings: Res<Settings>,
+) {
+    let unmuted: Vec<Entity> = removed.iter().collect();
+    for (entity, material, muted) in lights.iter() {
+        let color = match muted {
+            Some(muted) if muted.is_added() => COLOR_LIGHT_MUTED,
+            None if unmuted.contains(&entity) => settings.colors.light,
+            _ => continue,
+        };
+        if let Some(material) = materials.get_mut(material) {
+            material.color = color;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solo_overrides_mute() {
+        let (a, b) = (Entity::from_raw(1), Entity::from_raw(2));
+        let mut solo = Solo::default();
+        assert!(solo.admits(a, false));
+        assert!(!solo.admits(b, true));
+
+        solo.toggle(b);
+        assert!(!solo.admits(a, false));
+        assert!(solo.admits(b, true));
+
+        solo.toggle(b);
+        assert_eq!(solo.0, None);
+    }
+}
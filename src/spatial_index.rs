@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+
+use bevy::ecs as bevy_ecs;
+use bevy::prelude::*;
+use geo::{Intersects, Line, LineString, Polygon};
+use rstar::{RTree, RTreeObject, AABB};
+
+use crate::translucency::Transmittance;
+use crate::{calculate_vertices, Obstacle};
+
+#[derive(Clone)]
+pub struct IndexedObstacle {
+    pub entity: Entity,
+    pub vertices: [Vec2; 4],
+    pub polygon: Polygon<f32>,
+    pub transmittance: f32,
+}
+
+impl IndexedObstacle {
+    fn new(entity: Entity, transform: &Transform, transmittance: f32) -> Self {
+        let vertices = calculate_vertices(transform);
+        let polygon = Polygon::new(
+            LineString::from_iter(vertices.iter().map(|v| v.to_array())),
+            Vec::new(),
+        );
+        Self {
+            entity,
+            vertices,
+            polygon,
+            transmittance,
+        }
+    }
+
+    // 光源から見てこの障害物の影に完全に入っているか (半透明なら隠さない)
+    fn hides(&self, light_position: Vec2, other: &IndexedObstacle) -> bool {
+        self.transmittance == 0.0
+            && other.vertices.iter().all(|v| {
+                self.polygon
+                    .intersects(&Line::new(light_position.to_array(), v.to_array()))
+            })
+    }
+}
+
+impl PartialEq for IndexedObstacle {
+    fn eq(&self, other: &Self) -> bool {
+        self.entity == other.entity
+    }
+}
+
+impl RTreeObject for IndexedObstacle {
+    type Envelope = AABB<[f32; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        AABB::from_points(self.vertices.iter().map(|v| v.as_ref()))
+    }
+}
+
+/// The region a light can reach: a maximum distance and an optional cone
+/// given as `(direction, half_width)` in radians.
+#[derive(Clone, Copy, Default)]
+pub struct LightReach {
+    pub range: Option<f32>,
+    pub cone: Option<(f32, f32)>,
+}
+
+impl LightReach {
+    fn contains(&self, light_position: Vec2, obstacle: &IndexedObstacle) -> bool {
+        if let Some(range) = self.range {
+            let distance_2 = obstacle.envelope().distance_2(&light_position.to_array());
+            if distance_2 > range * range {
+                return false;
+            }
+        }
+        if let Some((direction, half_width)) = self.cone {
+            if half_width >= std::f32::consts::PI {
+                return true;
+            }
+            let axis = Vec2::from_angle(direction);
+            let inside = |v: Vec2| {
+                let ray = v - light_position;
+                ray == Vec2::ZERO || axis.angle_between(ray).abs() <= half_width
+            };
+            // 頂点が錐の中にあるか、錐の縁が障害物を横切るか
+            if obstacle.vertices.iter().any(|&v| inside(v)) {
+                return true;
+            }
+            let length = obstacle
+                .vertices
+                .iter()
+                .map(|&v| v.distance(light_position))
+                .fold(0.0, f32::max);
+            return [direction - half_width, direction + half_width]
+                .iter()
+                .any(|&angle| {
+                    let far = light_position + Vec2::from_angle(angle) * length;
+                    obstacle
+                        .polygon
+                        .intersects(&Line::new(light_position.to_array(), far.to_array()))
+                });
+        }
+        true
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ObstacleIndex {
+    tree: RTree<IndexedObstacle>,
+    entries: HashMap<Entity, IndexedObstacle>,
+}
+
+impl ObstacleIndex {
+    pub fn upsert(&mut self, entity: Entity, transform: &Transform, transmittance: f32) {
+        self.remove(entity);
+        let obstacle = IndexedObstacle::new(entity, transform, transmittance);
+        self.tree.insert(obstacle.clone());
+        self.entries.insert(entity, obstacle);
+    }
+
+    pub fn remove(&mut self, entity: Entity) {
+        if let Some(obstacle) = self.entries.remove(&entity) {
+            self.tree.remove(&obstacle);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &IndexedObstacle> {
+        self.entries.values()
+    }
+
+    /// Obstacles that can cast a shadow inside `world_boundary` for a light at
+    /// `light_position`, nearest first. Obstacles outside the light's reach or
+    /// entirely inside the shadow of a nearer obstacle are left out.
+    pub fn visible_from(
+        &self,
+        light_position: Vec2,
+        reach: &LightReach,
+        world_boundary: &(Vec2, Vec2),
+    ) -> Vec<&IndexedObstacle> {
+        let mut lower = world_boundary.0;
+        let mut upper = world_boundary.1;
+        if let Some(range) = reach.range {
+            lower = lower.max(light_position - range);
+            upper = upper.min(light_position + range);
+        }
+        if lower.cmpgt(upper).any() {
+            return Vec::new();
+        }
+
+        let mut candidates: Vec<(f32, &IndexedObstacle)> = self
+            .tree
+            .locate_in_envelope_intersecting(&AABB::from_corners(
+                lower.to_array(),
+                upper.to_array(),
+            ))
+            .filter(|obstacle| reach.contains(light_position, obstacle))
+            .map(|obstacle| {
+                (
+                    obstacle.envelope().distance_2(&light_position.to_array()),
+                    obstacle,
+                )
+            })
+            .collect();
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut visible: Vec<&IndexedObstacle> = Vec::new();
+        for (_, obstacle) in candidates {
+            if !visible
+                .iter()
+                .any(|occluder| occluder.hides(light_position, obstacle))
+            {
+                visible.push(obstacle);
+            }
+        }
+        visible
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn update_obstacle_index(
+    mut index: ResMut<ObstacleIndex>,
+    obstacles: Query<(Entity, Ref<Transform>, Option<Ref<Transmittance>>), With<Obstacle>>,
+    mut removed: RemovedComponents<Obstacle>,
+    mut removed_transmittance: RemovedComponents<Transmittance>,
+) {
+    for entity in removed.iter() {
+        index.remove(entity);
+    }
+    let cleared: Vec<Entity> = removed_transmittance.iter().collect();
+    for (entity, transform, transmittance) in obstacles.iter() {
+        if transform.is_changed()
+            || transmittance.as_ref().is_some_and(|t| t.is_changed())
+            || cleared.contains(&entity)
+        {
+            index.upsert(entity, &transform, transmittance.map_or(0.0, |t| t.0));
+        }
+    }
+}
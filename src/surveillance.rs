@@ -0,0 +1,339 @@
+use std::f32::consts::{PI, TAU};
+
+use bevy::ecs as bevy_ecs;
+use bevy::{prelude::*, sprite::MaterialMesh2dBundle};
+use geo::{LineString, MultiPolygon, Polygon};
+
+use crate::actions::{Action, Actions};
+use crate::animation::Timeline;
+use crate::beam::sector;
+use crate::geo_scaled::ScaledBooleanOps;
+use crate::polygon_mesh::PolygonMeshBuilder;
+use crate::shadow_task::ShadowTask;
+use crate::spatial_index::{LightReach, ObstacleIndex};
+use crate::{
+    calculate_light_shadow, world_polygon, Draggable, Selected, WorldCoords, WorldSize,
+    COLOR_BLIND_SPOT, COLOR_CAMERA, COLOR_COVERAGE, LIGHT_Z, SURVEILLANCE_Z,
+};
+
+const CAMERA_SIZE: f32 = 12.0;
+const ROTATE_STEP: f32 = PI / 12.0;
+
+#[derive(Clone, Copy)]
+pub enum Sweep {
+    Static,
+    /// Pans `amplitude` radians to either side of the direction every
+    /// `period` seconds.
+    Panning {
+        amplitude: f32,
+        period: f32,
+    },
+}
+
+/// A security camera. Unlike a light it only sees a sector: `fov` radians
+/// wide around `direction`, up to `range`.
+#[derive(Component, Clone, Copy)]
+pub struct Camera {
+    pub direction: f32,
+    pub fov: f32,
+    pub range: f32,
+    pub sweep: Sweep,
+}
+
+impl Camera {
+    /// Where the camera looks at `time` seconds.
+    pub fn direction_at(&self, time: f32) -> f32 {
+        match self.sweep {
+            Sweep::Static => self.direction,
+            Sweep::Panning { amplitude, period } => {
+                self.direction + amplitude * (TAU * time / period).sin()
+            }
+        }
+    }
+
+    fn reach(&self, direction: f32) -> LightReach {
+        LightReach {
+            range: Some(self.range),
+            cone: Some((direction, self.fov / 2.0)),
+        }
+    }
+}
+
+/// Whether the coverage and blind spot layer is shown.
+#[derive(Resource, Default)]
+pub struct ShowSurveillance(pub bool);
+
+#[derive(Resource, Default)]
+pub struct SurveillanceJob {
+    outdated: bool,
+    task: Option<ShadowTask<Surveillance>>,
+}
+
+pub struct Surveillance {
+    pub coverage: MultiPolygon<f32>,
+    pub blind_spots: MultiPolygon<f32>,
+}
+
+#[derive(Component)]
+pub struct SurveillanceLayer;
+
+fn to_polygon(points: impl IntoIterator<Item = Vec2>) -> MultiPolygon<f32> {
+    MultiPolygon::new(vec![Polygon::new(
+        LineString::from_iter(points.into_iter().map(|p| p.to_array())),
+        Vec::new(),
+    )])
+}
+
+/// What a camera at `position` looking towards `direction` sees past
+/// `obstacles`.
+pub fn calculate_viewshed(
+    position: Vec2,
+    direction: f32,
+    camera: &Camera,
+    obstacles: &[[Vec2; 4]],
+    world_boundary: (Vec2, Vec2),
+) -> MultiPolygon<f32> {
+    world_polygon(world_boundary)
+        .scaled_intersection(&sector(position, direction, camera.fov, camera.range), 1e1)
+        .scaled_difference(
+            &calculate_light_shadow(position, obstacles, world_boundary),
+            1e1,
+        )
+}
+
+/// The floor no camera sees, leaving out the obstacles themselves.
+pub fn calculate_blind_spots(
+    coverage: &MultiPolygon<f32>,
+    obstacles: &[[Vec2; 4]],
+    world_boundary: (Vec2, Vec2),
+) -> MultiPolygon<f32> {
+    obstacles.iter().fold(
+        world_polygon(world_boundary).scaled_difference(coverage, 1e1),
+        |fold, vertices| fold.scaled_difference(&to_polygon(vertices.iter().copied()), 1e1),
+    )
+}
+
+pub fn spawn_camera(commands: &mut Commands, position: Vec2, camera: Camera) {
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: COLOR_CAMERA,
+                ..default()
+            },
+            transform: Transform::from_translation(position.extend(LIGHT_Z))
+                .with_scale(Vec2::new(CAMERA_SIZE, CAMERA_SIZE / 2.0).extend(1.0))
+                .with_rotation(Quat::from_rotation_z(camera.direction)),
+            ..default()
+        },
+        camera,
+        Draggable,
+    ));
+}
+
+/// K places a static camera at the cursor.
+pub fn add_camera(
+    mut commands: Commands,
+    actions: Res<Actions>,
+    cursor_position: Res<WorldCoords>,
+) {
+    if actions.just_pressed(Action::AddCamera) {
+        spawn_camera(
+            &mut commands,
+            cursor_position.0,
+            Camera {
+                direction: 0.0,
+                fov: PI / 2.0,
+                range: 300.0,
+                sweep: Sweep::Static,
+            },
+        );
+    }
+}
+
+/// R turns the selected camera, and S shows or hides the surveillance layer.
+pub fn control_cameras(
+    mut cameras: Query<&mut Camera, With<Selected>>,
+    actions: Res<Actions>,
+    mut show: ResMut<ShowSurveillance>,
+    mut layers: Query<&mut Visibility, With<SurveillanceLayer>>,
+) {
+    if actions.just_pressed(Action::RotateCamera) {
+        for mut camera in cameras.iter_mut() {
+            camera.direction = (camera.direction + ROTATE_STEP).rem_euclid(TAU);
+        }
+    }
+    if actions.just_pressed(Action::ToggleSurveillance) {
+        show.0 = !show.0;
+        for mut visibility in layers.iter_mut() {
+            *visibility = if show.0 {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            };
+        }
+    }
+}
+
+pub fn sweep_cameras(timeline: Res<Timeline>, mut cameras: Query<(&mut Transform, Ref<Camera>)>) {
+    for (mut transform, camera) in cameras.iter_mut() {
+        if timeline.is_changed() || camera.is_changed() {
+            transform.rotation = Quat::from_rotation_z(camera.direction_at(timeline.time));
+        }
+    }
+}
+
+pub fn update_surveillance(
+    mut job: ResMut<SurveillanceJob>,
+    cameras: Query<(Ref<Transform>, &Camera)>,
+    mut removed: RemovedComponents<Camera>,
+    obstacle_index: Res<ObstacleIndex>,
+    world_size: Res<WorldSize>,
+) {
+    let camera_removed = removed.iter().count() > 0;
+    if camera_removed
+        || obstacle_index.is_changed()
+        || world_size.is_changed()
+        || cameras.iter().any(|(transform, _)| transform.is_changed())
+    {
+        job.outdated = true;
+    }
+    if !job.outdated || job.task.is_some() {
+        return;
+    }
+    job.outdated = false;
+
+    let world_boundary = world_size.boundary();
+    let views: Vec<(Vec2, f32, Camera, Vec<[Vec2; 4]>)> = cameras
+        .iter()
+        .map(|(transform, camera)| {
+            let position = transform.translation.truncate();
+            let direction = transform.rotation.to_euler(EulerRot::YXZ).2;
+            // ガラス越しには見通せる
+            let obstacles = obstacle_index
+                .visible_from(position, &camera.reach(direction), &world_boundary)
+                .into_iter()
+                .filter(|obstacle| obstacle.transmittance == 0.0)
+                .map(|obstacle| obstacle.vertices)
+                .collect();
+            (position, direction, *camera, obstacles)
+        })
+        .collect();
+    let obstacles: Vec<[Vec2; 4]> = obstacle_index
+        .iter()
+        .map(|obstacle| obstacle.vertices)
+        .collect();
+    job.task = Some(ShadowTask::spawn(move || {
+        let coverage = views.into_iter().fold(
+            MultiPolygon::new(Vec::new()),
+            |fold, (position, direction, camera, obstacles)| {
+                let viewshed =
+                    calculate_viewshed(position, direction, &camera, &obstacles, world_boundary);
+                fold.scaled_union(&viewshed, 1e1)
+            },
+        );
+        Surveillance {
+            blind_spots: calculate_blind_spots(&coverage, &obstacles, world_boundary),
+            coverage,
+        }
+    }));
+}
+
+pub fn apply_surveillance(
+    mut commands: Commands,
+    mut job: ResMut<SurveillanceJob>,
+    layers: Query<Entity, With<SurveillanceLayer>>,
+    show: Res<ShowSurveillance>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let Some(task) = &mut job.task else {
+        return;
+    };
+    if !task.is_finished() {
+        return;
+    }
+    let Some(surveillance) = job.task.take().and_then(ShadowTask::into_result) else {
+        return;
+    };
+    for entity in layers.iter() {
+        commands.entity(entity).despawn();
+    }
+    for (polygon, color) in [
+        (surveillance.coverage, COLOR_COVERAGE),
+        (surveillance.blind_spots, COLOR_BLIND_SPOT),
+    ] {
+        let mut builder = PolygonMeshBuilder::default();
+        builder.add_multi_polygon(&polygon);
+        if let Some((translation, mesh)) = builder.build() {
+            commands.spawn((
+                MaterialMesh2dBundle {
+                    mesh: meshes.add(mesh).into(),
+                    material: materials.add(ColorMaterial::from(color)),
+                    transform: Transform::from_translation(translation.extend(SURVEILLANCE_Z)),
+                    visibility: if show.0 {
+                        Visibility::Inherited
+                    } else {
+                        Visibility::Hidden
+                    },
+                    ..Default::default()
+                },
+                SurveillanceLayer,
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::{Intersects, Point};
+
+    #[test]
+    fn camera_sees_its_sector_up_to_obstacles() {
+        let camera = Camera {
+            direction: 0.0,
+            fov: PI / 2.0,
+            range: 200.0,
+            sweep: Sweep::Static,
+        };
+        let pillar = [
+            Vec2::new(90.0, 40.0),
+            Vec2::new(110.0, 40.0),
+            Vec2::new(110.0, 60.0),
+            Vec2::new(90.0, 60.0),
+        ];
+        let world_boundary = WorldSize::default().boundary();
+        let viewshed = calculate_viewshed(Vec2::ZERO, 0.0, &camera, &[pillar], world_boundary);
+        let blind_spots = calculate_blind_spots(&viewshed, &[pillar], world_boundary);
+
+        let at = |x: f32, y: f32| Point::new(x, y);
+        assert!(viewshed.intersects(&at(150.0, 0.0)));
+        // 後ろ、視野角の外、範囲外、柱の陰
+        for point in [
+            at(-50.0, 0.0),
+            at(50.0, 100.0),
+            at(250.0, 0.0),
+            at(150.0, 75.0),
+        ] {
+            assert!(!viewshed.intersects(&point));
+            assert!(blind_spots.intersects(&point));
+        }
+        assert!(!blind_spots.intersects(&at(100.0, 50.0)));
+    }
+
+    #[test]
+    fn panning_camera_sweeps_around_its_direction() {
+        let camera = Camera {
+            direction: 1.0,
+            fov: PI / 2.0,
+            range: 200.0,
+            sweep: Sweep::Panning {
+                amplitude: 0.5,
+                period: 4.0,
+            },
+        };
+        assert_eq!(camera.direction_at(0.0), 1.0);
+        assert_eq!(camera.direction_at(1.0), 1.5);
+        assert!((camera.direction_at(3.0) - 0.5).abs() < 1e-6);
+    }
+}
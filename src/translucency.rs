@@ -0,0 +1,230 @@
+use bevy::ecs as bevy_ecs;
+use bevy::prelude::*;
+use geo::MultiPolygon;
+
+use crate::actions::{Action, Actions};
+use crate::geo_scaled::ScaledBooleanOps;
+use crate::{calculate_shadow_polygon_from_obstacle, calculate_vertices, Obstacle, WorldCoords};
+
+/// The number of steps between lit and fully shadowed.
+pub const SHADE_LEVELS: u8 = 4;
+
+/// The share of light an obstacle lets through, from 0 (opaque) to 1.
+#[derive(Component, Clone, Copy)]
+pub struct Transmittance(pub f32);
+
+/// The shadow of one light, graded by how much of the light is blocked.
+///
+/// Entries are nested and sorted by level: each area is where at least
+/// `level / SHADE_LEVELS` of the light is blocked.
+#[derive(Clone, Default)]
+pub struct GradedShadow(Vec<(u8, MultiPolygon<f32>)>);
+
+impl GradedShadow {
+    pub fn levels(&self) -> impl Iterator<Item = u8> + '_ {
+        self.0.iter().map(|&(level, _)| level)
+    }
+
+    /// Where at least `level / SHADE_LEVELS` of the light is blocked.
+    pub fn at_least(&self, level: u8) -> MultiPolygon<f32> {
+        self.0
+            .iter()
+            .find(|&&(l, _)| l >= level)
+            .map_or_else(|| MultiPolygon::new(Vec::new()), |(_, area)| area.clone())
+    }
+
+    pub fn difference(self, lit: &MultiPolygon<f32>) -> Self {
+        Self(
+            self.0
+                .into_iter()
+                .map(|(level, area)| (level, area.scaled_difference(lit, 1e1)))
+                .collect(),
+        )
+    }
+
+    /// Blocks the light completely in `dark` as well.
+    pub fn union(self, dark: &MultiPolygon<f32>) -> Self {
+        Self(
+            self.0
+                .into_iter()
+                .map(|(level, area)| (level, area.scaled_union(dark, 1e1)))
+                .collect(),
+        )
+    }
+
+    /// Splits `room` by how much of the light reaches it, as areas and the
+    /// share of the light they get.
+    pub fn lit_areas(&self, room: &MultiPolygon<f32>) -> Vec<(MultiPolygon<f32>, f32)> {
+        let mut areas = Vec::new();
+        let mut lit = room.clone();
+        let mut share = 1.0;
+        for (level, area) in &self.0 {
+            areas.push((lit.scaled_difference(area, 1e1), share));
+            lit = area.clone();
+            share = 1.0 - *level as f32 / SHADE_LEVELS as f32;
+        }
+        areas.push((lit, share));
+        areas.retain(|(area, share)| *share > 0.0 && !area.0.is_empty());
+        areas
+    }
+}
+
+/// Grades the shadow of a light: `opaque` is blocked completely, and every
+/// translucent obstacle dims whatever lies behind it by its transmittance.
+pub fn calculate_graded_shadow(
+    light_position: Vec2,
+    opaque: MultiPolygon<f32>,
+    translucent: &[([Vec2; 4], f32)],
+    world_boundary: (Vec2, Vec2),
+) -> GradedShadow {
+    // 半透明の影の重なりごとに透過率を掛け合わせる
+    let mut regions: Vec<(MultiPolygon<f32>, f32)> = Vec::new();
+    for (vertices, transmittance) in translucent {
+        let shadow = MultiPolygon::new(vec![calculate_shadow_polygon_from_obstacle(
+            light_position,
+            vertices,
+            world_boundary,
+        )]);
+        let covered = regions
+            .iter()
+            .fold(MultiPolygon::new(Vec::new()), |fold, (region, _)| {
+                fold.scaled_union(region, 1e1)
+            });
+        let mut split: Vec<(MultiPolygon<f32>, f32)> = regions
+            .into_iter()
+            .flat_map(|(region, share)| {
+                [
+                    (
+                        region.scaled_intersection(&shadow, 1e1),
+                        share * transmittance,
+                    ),
+                    (region.scaled_difference(&shadow, 1e1), share),
+                ]
+            })
+            .collect();
+        split.push((shadow.scaled_difference(&covered, 1e1), *transmittance));
+        split.retain(|(region, _)| !region.0.is_empty());
+        regions = split;
+    }
+
+    let level_of = |share: f32| ((1.0 - share) * SHADE_LEVELS as f32).round() as u8;
+    let mut levels: Vec<u8> = regions
+        .iter()
+        .map(|&(_, share)| level_of(share))
+        .filter(|&level| level > 0)
+        .chain([SHADE_LEVELS])
+        .collect();
+    levels.sort();
+    levels.dedup();
+    GradedShadow(
+        levels
+            .into_iter()
+            .map(|level| {
+                let area = regions
+                    .iter()
+                    .filter(|&&(_, share)| level_of(share) >= level)
+                    .fold(opaque.clone(), |fold, (region, _)| {
+                        fold.scaled_union(region, 1e1)
+                    });
+                (level, area)
+            })
+            .collect(),
+    )
+}
+
+/// The colour `level` steps from `lit` towards `shadowed`.
+pub fn shade(lit: Color, shadowed: Color, level: u8) -> Color {
+    let t = level as f32 / SHADE_LEVELS as f32;
+    let [r, g, b, a] = Vec4::from(lit.as_rgba_f32())
+        .lerp(Vec4::from(shadowed.as_rgba_f32()), t)
+        .to_array();
+    Color::rgba(r, g, b, a)
+}
+
+pub fn cycle_obstacle_transmittance(
+    mut commands: Commands,
+    obstacles: Query<(Entity, &Transform, Option<&Transmittance>), With<Obstacle>>,
+    actions: Res<Actions>,
+    cursor_position: Res<WorldCoords>,
+) {
+    if !actions.just_pressed(Action::CycleTransmittance) {
+        return;
+    }
+    for (entity, transform, transmittance) in obstacles.iter() {
+        let vertices = calculate_vertices(transform);
+        let inside = (0..4).all(|i| {
+            let edge = vertices[(i + 1) % 4] - vertices[i];
+            edge.perp_dot(cursor_position.0 - vertices[i]) >= 0.0
+        });
+        if !inside {
+            continue;
+        }
+        match transmittance.map_or(0.0, |t| t.0) {
+            t if t >= 0.75 => {
+                commands.entity(entity).remove::<Transmittance>();
+            }
+            t => {
+                commands.entity(entity).insert(Transmittance(t + 0.25));
+            }
+        }
+    }
+}
+
+pub fn tint_translucent_obstacles(
+    mut obstacles: Query<(&mut Sprite, Option<Ref<Transmittance>>), With<Obstacle>>,
+    mut removed: RemovedComponents<Transmittance>,
+) {
+    let removed: Vec<Entity> = removed.iter().collect();
+    for entity in removed {
+        if let Ok((mut sprite, None)) = obstacles.get_mut(entity) {
+            sprite.color.set_a(1.0);
+        }
+    }
+    for (mut sprite, transmittance) in obstacles.iter_mut() {
+        if let Some(transmittance) = transmittance.filter(|t| t.is_changed()) {
+            sprite.color.set_a(1.0 - transmittance.0 * 0.8);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::{Intersects, Point};
+
+    #[test]
+    fn overlapping_glass_dims_further() {
+        let world_boundary = (Vec2::new(-480.0, -360.0), Vec2::new(480.0, 360.0));
+        let pane = |x: f32, half_height: f32| {
+            [
+                Vec2::new(x - 5.0, -half_height),
+                Vec2::new(x + 5.0, -half_height),
+                Vec2::new(x + 5.0, half_height),
+                Vec2::new(x - 5.0, half_height),
+            ]
+        };
+        let shadow = calculate_graded_shadow(
+            Vec2::ZERO,
+            MultiPolygon::new(Vec::new()),
+            &[(pane(100.0, 50.0), 0.5), (pane(200.0, 200.0), 0.5)],
+            world_boundary,
+        );
+
+        let level_at = |x: f32, y: f32| {
+            shadow
+                .levels()
+                .filter(|&level| shadow.at_least(level).intersects(&Point::new(x, y)))
+                .max()
+                .unwrap_or(0)
+        };
+        // 1 枚で半分、2 枚で 4 分の 3 を遮る
+        assert_eq!(level_at(-100.0, 0.0), 0);
+        assert_eq!(level_at(150.0, 0.0), 2);
+        assert_eq!(level_at(300.0, 0.0), 3);
+        assert_eq!(level_at(300.0, 250.0), 2);
+        assert_eq!(level_at(300.0, 340.0), 0);
+
+        let lit = shadow.lit_areas(&crate::world_polygon(world_boundary));
+        assert_eq!(lit.len(), 3);
+    }
+}
@@ -0,0 +1,494 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use bevy::ecs as bevy_ecs;
+use bevy::prelude::*;
+use geo::{Contains, MultiPolygon, Point};
+
+use crate::actions::{Action, Actions};
+use crate::animation::{facing, Spin, Timeline};
+use crate::beam::Beam;
+use crate::mirror::{calculate_reflections, BounceDepth, Mirror};
+use crate::raycast::{is_lit, transmission};
+use crate::solo::{Muted, Solo};
+use crate::spatial_index::ObstacleIndex;
+use crate::{
+    calculate_vertices, Light, Theta, WorldCoords, WorldSize, COLOR_VISITOR_DARK,
+    COLOR_VISITOR_LIT, VISITOR_Z,
+};
+
+const VISITOR_SIZE: f32 = 8.0;
+const VISITOR_SPEED: f32 = 60.0;
+// 経路探索の格子の大きさ
+const ROUTE_CELL: f32 = 10.0;
+// 区画の大きさ。列を A, B, ...、行を 1, 2, ... と上から呼ぶ
+const ZONE_SIZE: f32 = 120.0;
+
+/// A simulated visitor walking from waypoint to waypoint around obstacles.
+#[derive(Component)]
+pub struct Visitor {
+    pub waypoints: Vec<Vec2>,
+    pub next: usize,
+    pub speed: f32,
+    route: Vec<Vec2>,
+}
+
+impl Visitor {
+    pub fn new(waypoints: Vec<Vec2>) -> Self {
+        Self {
+            waypoints,
+            next: 0,
+            speed: VISITOR_SPEED,
+            route: Vec::new(),
+        }
+    }
+}
+
+/// When a visitor was out of the light, and the zones they crossed unlit.
+#[derive(Component, Default)]
+pub struct DarknessLog {
+    /// Timeline times the visitor entered and left the dark.
+    pub intervals: Vec<(f32, f32)>,
+    pub unlit_zones: Vec<String>,
+    in_dark: bool,
+}
+
+impl DarknessLog {
+    pub fn record(&mut self, time: f32, lit: bool, zone: String) {
+        if lit {
+            // 暗がりは明るくなった時点で終わる
+            if let Some(interval) = self.intervals.last_mut().filter(|_| self.in_dark) {
+                interval.1 = time;
+            }
+            self.in_dark = false;
+            return;
+        }
+        match self.intervals.last_mut() {
+            Some(interval) if self.in_dark => interval.1 = time,
+            _ => self.intervals.push((time, time)),
+        }
+        self.in_dark = true;
+        if !self.unlit_zones.contains(&zone) {
+            self.unlit_zones.push(zone);
+        }
+    }
+
+    pub fn dark_time(&self) -> f32 {
+        self.intervals.iter().map(|(start, end)| end - start).sum()
+    }
+}
+
+#[derive(Component)]
+pub struct VisitorReport;
+
+pub fn zone_at(point: Vec2, world_boundary: (Vec2, Vec2)) -> String {
+    let column = ((point.x - world_boundary.0.x) / ZONE_SIZE)
+        .floor()
+        .max(0.0) as u8;
+    let row = ((world_boundary.1.y - point.y) / ZONE_SIZE)
+        .floor()
+        .max(0.0) as u32;
+    format!("{}{}", (b'A' + column.min(25)) as char, row + 1)
+}
+
+fn distance_to_obstacle(point: Vec2, vertices: &[Vec2; 4]) -> f32 {
+    let inside = (0..4).all(|i| {
+        let edge = vertices[(i + 1) % 4] - vertices[i];
+        edge.perp_dot(point - vertices[i]) >= 0.0
+    });
+    if inside {
+        return 0.0;
+    }
+    (0..4)
+        .map(|i| {
+            let (a, b) = (vertices[i], vertices[(i + 1) % 4]);
+            let t =
+                ((point - a).dot(b - a) / a.distance_squared(b).max(f32::EPSILON)).clamp(0.0, 1.0);
+            point.distance(a.lerp(b, t))
+        })
+        .fold(f32::INFINITY, f32::min)
+}
+
+/// A route from `start` to `goal` keeping `clearance` away from `obstacles`,
+/// found with A* on a grid over the room. `None` if the goal can't be reached.
+pub fn find_route(
+    start: Vec2,
+    goal: Vec2,
+    obstacles: &[[Vec2; 4]],
+    clearance: f32,
+    world_boundary: (Vec2, Vec2),
+) -> Option<Vec<Vec2>> {
+    let (lower, upper) = world_boundary;
+    let columns = ((upper.x - lower.x) / ROUTE_CELL).ceil() as i32;
+    let rows = ((upper.y - lower.y) / ROUTE_CELL).ceil() as i32;
+    let cell_of = |p: Vec2| {
+        let c = ((p - lower) / ROUTE_CELL).floor();
+        (
+            (c.x as i32).clamp(0, columns - 1),
+            (c.y as i32).clamp(0, rows - 1),
+        )
+    };
+    let center = |(x, y): (i32, i32)| lower + (Vec2::new(x as f32, y as f32) + 0.5) * ROUTE_CELL;
+    let blocked = |cell: (i32, i32)| {
+        obstacles
+            .iter()
+            .any(|vertices| distance_to_obstacle(center(cell), vertices) < clearance)
+    };
+    let (from, to) = (cell_of(start), cell_of(goal));
+    if blocked(to) {
+        return None;
+    }
+
+    // 8 近傍、斜めは 14、縦横は 10
+    let heuristic = |(x, y): (i32, i32)| {
+        let (dx, dy) = ((x - to.0).abs(), (y - to.1).abs());
+        10 * dx.max(dy) + 4 * dx.min(dy)
+    };
+    let mut open = BinaryHeap::from([Reverse((heuristic(from), 0, from))]);
+    let mut costs = HashMap::from([(from, 0)]);
+    let mut came_from: HashMap<(i32, i32), (i32, i32)> = HashMap::new();
+    let mut is_blocked: HashMap<(i32, i32), bool> = HashMap::new();
+    let mut blocked_at =
+        |cell: (i32, i32)| *is_blocked.entry(cell).or_insert_with(|| blocked(cell));
+    while let Some(Reverse((_, cost, cell))) = open.pop() {
+        if cell == to {
+            let mut route = vec![goal];
+            let mut cell = cell;
+            while let Some(&previous) = came_from.get(&cell) {
+                if previous != from {
+                    route.push(center(previous));
+                }
+                cell = previous;
+            }
+            route.reverse();
+            return Some(route);
+        }
+        if costs.get(&cell).is_some_and(|&c| c < cost) {
+            continue;
+        }
+        for (dx, dy) in [
+            (1, 0),
+            (-1, 0),
+            (0, 1),
+            (0, -1),
+            (1, 1),
+            (1, -1),
+            (-1, 1),
+            (-1, -1),
+        ] {
+            let next = (cell.0 + dx, cell.1 + dy);
+            if next.0 < 0 || next.1 < 0 || next.0 >= columns || next.1 >= rows || blocked_at(next) {
+                continue;
+            }
+            // 角をすり抜けない
+            if dx != 0
+                && dy != 0
+                && (blocked_at((cell.0 + dx, cell.1)) || blocked_at((cell.0, cell.1 + dy)))
+            {
+                continue;
+            }
+            let next_cost = cost + if dx != 0 && dy != 0 { 14 } else { 10 };
+            if costs.get(&next).is_none_or(|&c| next_cost < c) {
+                costs.insert(next, next_cost);
+                came_from.insert(next, cell);
+                open.push(Reverse((next_cost + heuristic(next), next_cost, next)));
+            }
+        }
+    }
+    None
+}
+
+pub fn spawn_visitor(commands: &mut Commands, waypoints: Vec<Vec2>) {
+    let start = waypoints.first().copied().unwrap_or_default();
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: COLOR_VISITOR_LIT,
+                ..default()
+            },
+            transform: Transform::from_translation(start.extend(VISITOR_Z))
+                .with_scale(Vec2::splat(VISITOR_SIZE).extend(1.0)),
+            ..default()
+        },
+        Visitor::new(waypoints),
+        DarknessLog::default(),
+    ));
+}
+
+/// V sends a visitor from the cursor to the opposite side of the room and back.
+pub fn add_visitor(
+    mut commands: Commands,
+    actions: Res<Actions>,
+    cursor_position: Res<WorldCoords>,
+) {
+    if actions.just_pressed(Action::AddVisitor) {
+        spawn_visitor(&mut commands, vec![cursor_position.0, -cursor_position.0]);
+    }
+}
+
+pub fn walk_visitors(
+    timeline: Res<Timeline>,
+    time: Res<Time>,
+    obstacle_index: Res<ObstacleIndex>,
+    world_size: Res<WorldSize>,
+    mut visitors: Query<(&mut Transform, &mut Visitor)>,
+) {
+    if obstacle_index.is_changed() || world_size.is_changed() {
+        for (_, mut visitor) in visitors.iter_mut() {
+            visitor.route.clear();
+        }
+    }
+    if !timeline.playing {
+        return;
+    }
+    let obstacles: Vec<[Vec2; 4]> = obstacle_index
+        .iter()
+        .map(|obstacle| obstacle.vertices)
+        .collect();
+    for (mut transform, mut visitor) in visitors.iter_mut() {
+        if visitor.waypoints.is_empty() {
+            continue;
+        }
+        let mut position = transform.translation.truncate();
+        if visitor.route.is_empty() {
+            let goal = visitor.waypoints[visitor.next];
+            match find_route(
+                position,
+                goal,
+                &obstacles,
+                VISITOR_SIZE / 2.0,
+                world_size.boundary(),
+            ) {
+                Some(route) => visitor.route = route,
+                None => {
+                    visitor.next = (visitor.next + 1) % visitor.waypoints.len();
+                    continue;
+                }
+            }
+        }
+        let mut step = visitor.speed * time.delta_seconds();
+        while let Some(&target) = visitor.route.first() {
+            let distance = position.distance(target);
+            if distance > step {
+                position += (target - position) / distance * step;
+                break;
+            }
+            position = target;
+            step -= distance;
+            visitor.route.remove(0);
+        }
+        if visitor.route.is_empty() {
+            visitor.next = (visitor.next + 1) % visitor.waypoints.len();
+        }
+        transform.translation = position.extend(transform.translation.z);
+    }
+}
+
+/// Logs when visitors are in the dark. A visitor is lit by the same rule as
+/// the shadows: inside a light's beam and seen either directly, through
+/// glass or in a mirror. The mirrored light of each light is kept until the
+/// light, the obstacles, the mirrors or the bounce depth change.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn track_visitor_light(
+    timeline: Res<Timeline>,
+    lights: Query<
+        (
+            Entity,
+            Ref<Transform>,
+            Option<&Beam>,
+            Option<&Theta>,
+            Option<&Spin>,
+            Option<&Muted>,
+        ),
+        With<Light>,
+    >,
+    solo: Res<Solo>,
+    mirrors: Query<(&Transform, Ref<Mirror>)>,
+    mut removed_mirrors: RemovedComponents<Mirror>,
+    bounce_depth: Res<BounceDepth>,
+    obstacle_index: Res<ObstacleIndex>,
+    world_size: Res<WorldSize>,
+    mut reflections: Local<HashMap<Entity, MultiPolygon<f32>>>,
+    mut visitors: Query<(&Transform, &mut DarknessLog, &mut Sprite), With<Visitor>>,
+) {
+    // 止まっている間の変更も見落とさないよう、先に古い反射を捨てる
+    if removed_mirrors.iter().count() > 0
+        || mirrors.iter().any(|(_, mirror)| mirror.is_changed())
+        || obstacle_index.is_changed()
+        || bounce_depth.is_changed()
+        || world_size.is_changed()
+    {
+        reflections.clear();
+    }
+    reflections.retain(|&entity, _| {
+        lights
+            .get(entity)
+            .is_ok_and(|(_, transform, ..)| !transform.is_changed())
+    });
+    if !timeline.playing || visitors.is_empty() {
+        return;
+    }
+
+    let all_obstacles: Vec<[Vec2; 4]> = obstacle_index
+        .iter()
+        .map(|obstacle| obstacle.vertices)
+        .collect();
+    let mirror_edges: Vec<(Vec2, Vec2)> = mirrors
+        .iter()
+        .flat_map(|(transform, mirror)| {
+            mirror
+                .edges(&calculate_vertices(transform))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    let admitted: Vec<_> = lights
+        .iter()
+        .filter(|(entity, .., muted)| solo.admits(*entity, muted.is_some()))
+        .collect();
+    for (entity, transform, ..) in &admitted {
+        reflections.entry(*entity).or_insert_with(|| {
+            if mirror_edges.is_empty() {
+                return MultiPolygon::new(Vec::new());
+            }
+            calculate_reflections(
+                transform.translation.truncate(),
+                &all_obstacles,
+                &mirror_edges,
+                bounce_depth.0,
+                world_size.boundary(),
+            )
+        });
+    }
+    let lights: Vec<_> = admitted
+        .into_iter()
+        .map(|(entity, transform, beam, theta, spin, _)| {
+            let direction = facing(theta, spin, timeline.time);
+            (
+                transform.translation.truncate(),
+                beam.map(|&beam| (beam, direction)),
+                &reflections[&entity],
+            )
+        })
+        .collect();
+    let (opaque, translucent): (Vec<_>, Vec<_>) = obstacle_index
+        .iter()
+        .partition(|obstacle| obstacle.transmittance == 0.0);
+    let opaque: Vec<[Vec2; 4]> = opaque
+        .into_iter()
+        .map(|obstacle| obstacle.vertices)
+        .collect();
+    let translucent: Vec<([Vec2; 4], f32)> = translucent
+        .into_iter()
+        .map(|obstacle| (obstacle.vertices, obstacle.transmittance))
+        .collect();
+    for (transform, mut log, mut sprite) in visitors.iter_mut() {
+        let position = transform.translation.truncate();
+        let lit = lights.iter().any(|(light, beam, reflected)| {
+            let in_beam =
+                beam.is_none_or(|(beam, direction)| beam.covers(*light, direction, position));
+            let seen = is_lit(*light, position, &opaque)
+                && transmission(*light, position, &translucent) > 0.0;
+            in_beam && (seen || reflected.contains(&Point::new(position.x, position.y)))
+        });
+        log.record(timeline.time, lit, zone_at(position, world_size.boundary()));
+        sprite.color = if lit {
+            COLOR_VISITOR_LIT
+        } else {
+            COLOR_VISITOR_DARK
+        };
+    }
+}
+
+pub fn spawn_visitor_report(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 16.0,
+                color: Color::BLACK,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(8.0),
+            right: Val::Px(8.0),
+            ..default()
+        }),
+        VisitorReport,
+    ));
+}
+
+pub fn update_visitor_report(
+    timeline: Res<Timeline>,
+    visitors: Query<&DarknessLog, With<Visitor>>,
+    mut reports: Query<&mut Text, With<VisitorReport>>,
+) {
+    if !timeline.is_changed() {
+        return;
+    }
+    let lines: Vec<String> = visitors
+        .iter()
+        .enumerate()
+        .map(|(i, log)| {
+            format!(
+                "Visitor {}: {:.1} s in the dark ({} times), unlit in {}",
+                i + 1,
+                log.dark_time(),
+                log.intervals.len(),
+                if log.unlit_zones.is_empty() {
+                    "-".to_string()
+                } else {
+                    log.unlit_zones.join(", ")
+                },
+            )
+        })
+        .collect();
+    for mut text in reports.iter_mut() {
+        text.sections[0].value = lines.join("\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORLD_BOUNDARY: (Vec2, Vec2) = (Vec2::new(-480.0, -360.0), Vec2::new(480.0, 360.0));
+
+    #[test]
+    fn route_goes_around_a_wall() {
+        let wall = [
+            Vec2::new(-10.0, -200.0),
+            Vec2::new(10.0, -200.0),
+            Vec2::new(10.0, 200.0),
+            Vec2::new(-10.0, 200.0),
+        ];
+        let start = Vec2::new(-100.0, 0.0);
+        let goal = Vec2::new(100.0, 0.0);
+        let route = find_route(start, goal, &[wall], 4.0, WORLD_BOUNDARY).unwrap();
+
+        assert_eq!(route.last(), Some(&goal));
+        assert!(std::iter::once(start)
+            .chain(route.iter().copied())
+            .all(|p| distance_to_obstacle(p, &wall) >= 4.0));
+        assert!(route.iter().any(|p| p.y.abs() > 200.0));
+        assert!(find_route(start, Vec2::ZERO, &[wall], 4.0, WORLD_BOUNDARY).is_none());
+    }
+
+    #[test]
+    fn darkness_is_logged_per_interval_and_zone() {
+        let mut log = DarknessLog::default();
+        log.record(0.0, true, "A1".to_string());
+        log.record(1.0, false, "A1".to_string());
+        log.record(2.0, false, "B1".to_string());
+        log.record(3.0, true, "B1".to_string());
+        log.record(4.0, false, "A1".to_string());
+        log.record(4.5, false, "A1".to_string());
+
+        assert_eq!(log.intervals, vec![(1.0, 3.0), (4.0, 4.5)]);
+        assert_eq!(log.dark_time(), 2.5);
+        assert_eq!(log.unlit_zones, vec!["A1", "B1"]);
+        assert_eq!(zone_at(Vec2::new(-470.0, 350.0), WORLD_BOUNDARY), "A1");
+        assert_eq!(zone_at(Vec2::new(0.0, 0.0), WORLD_BOUNDARY), "E4");
+    }
+}